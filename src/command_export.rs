@@ -0,0 +1,302 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use orfail::OrFail;
+
+use crate::{
+    command_view::TimeSeriesArgs,
+    ignore_broken_pipe,
+    metrics::{Number, RepresentativeValue, TimeSeries, TimeSeriesSegment},
+};
+
+/// Export the segmented time series built from the results of the `poll` command (e.g., as CSV).
+#[derive(Debug, clap::Args)]
+pub struct ExportCommand {
+    /// Path to the file that contains the outputs from executing the `poll` command.
+    ///
+    /// If `-` is specified, the outputs are read from stdin until EOF.
+    metrics_jsonl_file: PathBuf,
+
+    #[clap(flatten)]
+    series: TimeSeriesArgs,
+
+    /// Output format.
+    #[clap(long, value_enum, default_value_t = ExportFormat::Csv)]
+    format: ExportFormat,
+
+    /// Table layout.
+    #[clap(long, value_enum, default_value_t = ExportLayout::Wide)]
+    layout: ExportLayout,
+
+    /// If specified, the values of each target are exported instead of the aggregated values.
+    #[clap(long)]
+    per_target: bool,
+}
+
+impl ExportCommand {
+    pub fn run(self) -> orfail::Result<()> {
+        let (ts, invalid_lines) = self.series.load(&self.metrics_jsonl_file).or_fail()?;
+        if invalid_lines > 0 {
+            eprintln!("Skipped {invalid_lines} invalid line(s)");
+        }
+        let mut writer = BufWriter::new(std::io::stdout());
+        let result = match (self.format, self.layout) {
+            (ExportFormat::Csv, ExportLayout::Wide) => self.write_wide(&ts, &mut writer),
+            (ExportFormat::Csv, ExportLayout::Long) => self.write_long(&ts, &mut writer),
+        }
+        .and_then(|()| writer.flush());
+        ignore_broken_pipe(result)
+    }
+
+    // One row per segment and two columns (value and delta) per metric (or per metric and target).
+    fn write_wide<W: Write>(&self, ts: &TimeSeries, writer: &mut W) -> std::io::Result<()> {
+        let columns = ts
+            .segments
+            .values()
+            .flat_map(|segment| self.cells(segment).map(|cell| (cell.metric, cell.target)))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .enumerate()
+            .map(|(i, column)| (column, i))
+            .collect::<BTreeMap<_, _>>();
+
+        let mut header = vec!["timestamp".to_owned()];
+        for (metric, target) in columns.keys() {
+            let name = match target {
+                Some(target) => format!("{metric}@{target}"),
+                None => metric.to_string(),
+            };
+            let delta_name = format!("{name}:delta");
+            header.extend([name, delta_name]);
+        }
+        write_csv_row(writer, header)?;
+
+        for segment in ts.segments.values() {
+            let mut row = vec![vec![String::new(); 2]; columns.len()];
+            for cell in self.cells(segment) {
+                row[columns[&(cell.metric, cell.target)]] =
+                    vec![value_text(cell.value), delta_text(cell.delta)];
+            }
+            write_csv_row(
+                writer,
+                std::iter::once(segment.start_time.as_secs_f64().to_string())
+                    .chain(row.into_iter().flatten()),
+            )?;
+        }
+        Ok(())
+    }
+
+    // One row per segment and metric (or per segment, metric and target).
+    fn write_long<W: Write>(&self, ts: &TimeSeries, writer: &mut W) -> std::io::Result<()> {
+        write_csv_row(writer, ["timestamp", "metric", "target", "value", "delta"])?;
+        for segment in ts.segments.values() {
+            let timestamp = segment.start_time.as_secs_f64().to_string();
+            for cell in self.cells(segment) {
+                write_csv_row(
+                    writer,
+                    [
+                        timestamp.clone(),
+                        cell.metric.to_owned(),
+                        cell.target.unwrap_or_default().to_owned(),
+                        value_text(cell.value),
+                        delta_text(cell.delta),
+                    ],
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    // Like `summary`, values carried forward (see `--carry-forward`) are not exported as samples.
+    fn cells<'a>(&self, segment: &'a TimeSeriesSegment) -> Box<dyn Iterator<Item = Cell<'a>> + 'a> {
+        if self.per_target {
+            Box::new(
+                segment
+                    .target_segment_values
+                    .iter()
+                    .flat_map(|(target, values)| {
+                        values.iter().filter(|(_, value)| !value.is_carried()).map(
+                            move |(metric, value)| Cell {
+                                metric,
+                                target: Some(target),
+                                value: Some(&value.value),
+                                delta: value.delta,
+                            },
+                        )
+                    }),
+            )
+        } else {
+            Box::new(
+                segment
+                    .aggregated_values
+                    .iter()
+                    .filter(|(metric, _)| segment.has_samples(metric))
+                    .map(|(metric, value)| Cell {
+                        metric,
+                        target: None,
+                        value: value.value.as_ref(),
+                        delta: value.delta,
+                    }),
+            )
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum ExportFormat {
+    Csv,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum ExportLayout {
+    /// One row per interval, with value and delta columns for each metric.
+    ///
+    /// The delta column of a metric is named `${METRIC}:delta`.
+    /// With `--per-target`, the columns are named `${METRIC}@${TARGET}` and `${METRIC}@${TARGET}:delta`.
+    Wide,
+
+    /// One row per interval and metric, with `timestamp`, `metric`, `target`, `value` and `delta` columns.
+    Long,
+}
+
+#[derive(Debug)]
+struct Cell<'a> {
+    metric: &'a str,
+    target: Option<&'a str>,
+    value: Option<&'a RepresentativeValue>,
+    delta: Option<Number>,
+}
+
+// Unlike the viewer, numbers are written without thousands separators so that they can be parsed.
+fn value_text(value: Option<&RepresentativeValue>) -> String {
+    match value {
+        None => String::new(),
+        Some(RepresentativeValue::Number(v)) => serde_json::to_string(v).expect("unreachable"),
+        Some(RepresentativeValue::Set(vs)) => serde_json::to_string(vs).expect("unreachable"),
+    }
+}
+
+fn delta_text(delta: Option<Number>) -> String {
+    delta
+        .map(|v| serde_json::to_string(&v).expect("unreachable"))
+        .unwrap_or_default()
+}
+
+fn write_csv_row<W, I, T>(writer: &mut W, fields: I) -> std::io::Result<()>
+where
+    W: Write,
+    I: IntoIterator<Item = T>,
+    T: AsRef<str>,
+{
+    let fields = fields
+        .into_iter()
+        .map(|field| {
+            let field = field.as_ref();
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_owned()
+            }
+        })
+        .collect::<Vec<_>>();
+    writeln!(writer, "{}", fields.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse_args;
+
+    const RECORDS: &str = r#"
+{"target":"a","timestamp":0,"metrics":{"v":10,"s":"x,y"}}
+{"target":"b","timestamp":0,"metrics":{"v":1}}
+{"target":"a","timestamp":2,"metrics":{"v":30}}
+{"target":"b","timestamp":3,"metrics":{"v":4}}
+"#;
+
+    fn export(args: &[&str]) -> String {
+        let command = parse_args::<ExportCommand>(&[&["-"], args].concat()).expect("valid args");
+        let (ts, _) = command
+            .series
+            .read_records(RECORDS.as_bytes())
+            .expect("valid records");
+
+        let mut output = Vec::new();
+        match command.layout {
+            ExportLayout::Wide => command.write_wide(&ts, &mut output),
+            ExportLayout::Long => command.write_long(&ts, &mut output),
+        }
+        .expect("write");
+        String::from_utf8(output).expect("UTF-8")
+    }
+
+    #[test]
+    fn csv_quoting() {
+        let mut output = Vec::new();
+        write_csv_row(&mut output, ["a", "b,c", "say \"hi\"", "x\ny", ""]).expect("write");
+        assert_eq!(
+            String::from_utf8(output).expect("UTF-8"),
+            "a,\"b,c\",\"say \"\"hi\"\"\",\"x\ny\",\n"
+        );
+    }
+
+    #[test]
+    fn wide_layout() {
+        let csv = export(&[]);
+        let expected = r#"timestamp,s,s:delta,v,v:delta
+0,"[""x,y""]",,11,
+2,,,31,10
+3,,,34,3
+"#;
+        assert_eq!(csv, expected);
+    }
+
+    #[test]
+    fn long_layout() {
+        let csv = export(&["--layout=long"]);
+        let expected = r#"timestamp,metric,target,value,delta
+0,s,,"[""x,y""]",
+0,v,,11,
+2,v,,31,10
+3,v,,34,3
+"#;
+        assert_eq!(csv, expected);
+
+        // Without carrying forward, the aggregated values only cover the sampled targets.
+        let csv = export(&["--layout=long", "--carry-forward=0"]);
+        let expected = r#"timestamp,metric,target,value,delta
+0,s,,"[""x,y""]",
+0,v,,11,
+2,v,,30,9
+3,v,,4,-26
+"#;
+        assert_eq!(csv, expected);
+    }
+
+    #[test]
+    fn per_target_long_layout() {
+        let csv = export(&["--per-target", "--layout=long"]);
+        let expected = r#"timestamp,metric,target,value,delta
+0,s,a,"[""x,y""]",
+0,v,a,10,
+0,v,b,1,
+2,v,a,30,10
+3,v,b,4,1
+"#;
+        assert_eq!(csv, expected);
+    }
+
+    #[test]
+    fn per_target_wide_layout() {
+        let csv = export(&["--per-target"]);
+        let expected = r#"timestamp,s@a,s@a:delta,v@a,v@a:delta,v@b,v@b:delta
+0,"[""x,y""]",,10,,1,
+2,,,30,10,,
+3,,,,,4,1
+"#;
+        assert_eq!(csv, expected);
+    }
+}
@@ -1,13 +1,25 @@
-use std::sync::mpsc;
+use std::{
+    collections::BTreeSet,
+    io::Write,
+    num::NonZeroU64,
+    path::{Path, PathBuf},
+    sync::mpsc,
+};
 
 use orfail::OrFail;
+use serde::Deserialize;
 
 use crate::{
-    num::SecondsU64,
+    ignore_broken_pipe,
+    metrics::Record,
+    num::{MillisNonZeroU64, MillisU64},
     poller::{PollTarget, Poller},
 };
 
-const YEAR: SecondsU64 = SecondsU64::new(364 * 24 * 60 * 60);
+const YEAR: MillisU64 = MillisU64::from_secs(364 * 24 * 60 * 60);
+
+const DEFAULT_POLL_INTERVAL: MillisNonZeroU64 =
+    MillisNonZeroU64::new(NonZeroU64::new(1000).unwrap());
 
 /// Poll the metrics of the specified targets and output the results in JSON Lines format to stdout.
 #[derive(Debug, clap::Args)]
@@ -15,34 +27,323 @@ pub struct PollCommand {
     /// JSON objects to specify polling targets.
     pub targets: Vec<PollTarget>,
 
-    /// Polling interval duration in seconds.
-    #[clap(short = 'i', long, default_value = "1")]
-    pub poll_interval: SecondsU64,
+    /// Path to a JSON or TOML file that defines polling targets and default settings.
+    ///
+    /// Settings given as command-line options take precedence over the ones in the file.
+    #[clap(short, long)]
+    pub config: Option<PathBuf>,
+
+    /// Polling interval duration (e.g., `1`, `500ms`, `5m`) [default: 1].
+    ///
+    /// A number without a unit is interpreted as seconds.
+    #[clap(short = 'i', long)]
+    pub poll_interval: Option<MillisNonZeroU64>,
 
-    /// Total duration of polling in seconds.
+    /// Total duration of polling (e.g., `3600`, `1h30m`, `2d`).
     #[clap(short, long)]
-    pub poll_duration: Option<SecondsU64>,
+    pub poll_duration: Option<MillisU64>,
+
+    /// Timeout for each poll (e.g., `10s`). Targets can override this with their `timeout` field.
+    #[clap(short = 't', long)]
+    pub poll_timeout: Option<MillisNonZeroU64>,
+
+    /// If specified, failed polls are output as records having an `error` object instead of being dropped.
+    #[clap(short = 'e', long)]
+    pub record_errors: bool,
 }
 
 impl PollCommand {
-    pub fn run(self) -> orfail::Result<()> {
+    pub fn run(mut self) -> orfail::Result<()> {
+        let config = self
+            .config
+            .as_deref()
+            .map(PollConfig::load)
+            .transpose()
+            .or_fail()?;
+        self.merge_config(config).or_fail()?;
+        let poll_interval = self.poll_interval.unwrap_or(DEFAULT_POLL_INTERVAL);
+        (!self.targets.is_empty()).or_fail_with(|()| "no polling targets".to_owned())?;
+
         let (record_tx, record_rx) = mpsc::channel();
 
         let poll_duration = self.poll_duration.unwrap_or(YEAR);
         for target in self.targets {
             Poller::start(
                 target,
-                self.poll_interval.to_duration(),
+                poll_interval.to_duration(),
                 poll_duration.to_duration(),
+                self.poll_timeout.map(|t| t.to_duration()),
+                self.record_errors,
                 record_tx.clone(),
             );
         }
         std::mem::drop(record_tx);
 
-        while let Ok(record) = record_rx.recv() {
-            println!("{}", serde_json::to_string(&record).or_fail()?);
+        // Stops when all the pollers have finished or the reader side (e.g., `magpies view -`) has exited.
+        ignore_broken_pipe(write_records(record_rx))
+    }
+
+    // Validates the targets given as arguments and merges the config (if any) into `self`.
+    //
+    // The targets in the config have already been validated by `PollConfig::load()`.
+    fn merge_config(&mut self, config: Option<PollConfig>) -> orfail::Result<()> {
+        let mut names = BTreeSet::new();
+        for (i, target) in self.targets.iter().enumerate() {
+            let location = format!("targets argument {i} ({:?})", target.target);
+            validate_target(target, &location, &mut names).or_fail()?;
         }
 
+        let Some(config) = config else {
+            return Ok(());
+        };
+        for target in &config.targets {
+            (!names.contains(&target.target)).or_fail_with(|()| {
+                format!(
+                    "target {:?} is defined in both the config file and the arguments",
+                    target.target
+                )
+            })?;
+        }
+        let mut targets = config.targets;
+        targets.append(&mut self.targets);
+        self.targets = targets;
+        self.poll_interval = self.poll_interval.or(config.poll_interval);
+        self.poll_duration = self.poll_duration.or(config.poll_duration);
+        self.poll_timeout = self.poll_timeout.or(config.poll_timeout);
+        self.record_errors |= config.record_errors;
         Ok(())
     }
 }
+
+/// Contents of the file specified by `--config`.
+#[derive(Debug)]
+pub struct PollConfig {
+    pub poll_interval: Option<MillisNonZeroU64>,
+    pub poll_duration: Option<MillisU64>,
+    pub poll_timeout: Option<MillisNonZeroU64>,
+    pub record_errors: bool,
+    pub targets: Vec<PollTarget>,
+}
+
+impl PollConfig {
+    /// Loads a config file. The format is TOML if the file extension is `.toml`, otherwise JSON.
+    pub fn load(path: &Path) -> orfail::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .or_fail_with(|e| format!("failed to read {}: {e}", path.display()))?;
+        Self::parse(path, &text)
+    }
+
+    // `path` is used to determine the format and to point at the offending entry on error.
+    fn parse(path: &Path, text: &str) -> orfail::Result<Self> {
+        let is_toml = path.extension().is_some_and(|ext| ext == "toml");
+        let value: serde_json::Value = if is_toml {
+            toml::from_str(text)
+                .or_fail_with(|e| format!("failed to parse {}: {e}", path.display()))?
+        } else {
+            serde_json::from_str(text)
+                .or_fail_with(|e| format!("failed to parse {}: {e}", path.display()))?
+        };
+
+        let raw: RawPollConfig = serde_json::from_value(value)
+            .or_fail_with(|e| format!("invalid config {}: {e}", path.display()))?;
+
+        let mut targets = Vec::new();
+        let mut names = BTreeSet::new();
+        for (i, value) in raw.targets.into_iter().enumerate() {
+            let location = match value.get("target").and_then(|v| v.as_str()) {
+                Some(name) => format!("{}: targets[{i}] ({name:?})", path.display()),
+                None => format!("{}: targets[{i}]", path.display()),
+            };
+            let target: PollTarget = serde_json::from_value(value)
+                .or_fail_with(|e| format!("{location}: invalid target: {e}"))?;
+            validate_target(&target, &location, &mut names).or_fail()?;
+            targets.push(target);
+        }
+
+        Ok(Self {
+            poll_interval: raw.poll_interval,
+            poll_duration: raw.poll_duration,
+            poll_timeout: raw.poll_timeout,
+            record_errors: raw.record_errors,
+            targets,
+        })
+    }
+}
+
+// `names` holds the names of the targets validated so far.
+fn write_records(record_rx: mpsc::Receiver<Record>) -> std::io::Result<()> {
+    let mut stdout = std::io::stdout();
+    while let Ok(record) = record_rx.recv() {
+        let line = serde_json::to_string(&record)?;
+        writeln!(stdout, "{line}")?;
+    }
+    Ok(())
+}
+
+fn validate_target(
+    target: &PollTarget,
+    location: &str,
+    names: &mut BTreeSet<String>,
+) -> orfail::Result<()> {
+    (!target.target.is_empty())
+        .or_fail_with(|()| format!("{location}: target name must not be empty"))?;
+    names
+        .insert(target.target.clone())
+        .or_fail_with(|()| format!("{location}: duplicate target name"))?;
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawPollConfig {
+    #[serde(default)]
+    poll_interval: Option<MillisNonZeroU64>,
+
+    #[serde(default)]
+    poll_duration: Option<MillisU64>,
+
+    #[serde(default)]
+    poll_timeout: Option<MillisNonZeroU64>,
+
+    #[serde(default)]
+    record_errors: bool,
+
+    // Targets are deserialized one by one to point at the offending entry on error.
+    #[serde(default)]
+    targets: Vec<serde_json::Value>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse_args;
+
+    fn parse_error(path: &str, text: &str) -> String {
+        PollConfig::parse(Path::new(path), text)
+            .expect_err("invalid config")
+            .message
+    }
+
+    #[test]
+    fn parse_config() {
+        let config = PollConfig::parse(
+            Path::new("targets.toml"),
+            r#"
+poll_interval = "500ms"
+
+[[targets]]
+target = "local"
+command_path = "echo"
+
+[[targets]]
+target = "api"
+url = "http://127.0.0.1:8080/"
+interval = 10
+"#,
+        )
+        .expect("valid config");
+        assert_eq!(config.poll_interval.map(|t| t.get()), Some(500));
+        assert_eq!(config.targets.len(), 2);
+        assert_eq!(config.targets[1].target, "api");
+    }
+
+    #[test]
+    fn invalid_config_entries() {
+        let error = parse_error("targets.toml", "[[targets]]\ntarget = ");
+        assert!(error.starts_with("failed to parse targets.toml"), "{error}");
+
+        let error = parse_error(
+            "targets.json",
+            r#"{"targets": [{"target": "a", "command_path": "echo"}, {"target": "b", "url": 1}]}"#,
+        );
+        assert!(
+            error.starts_with(r#"targets.json: targets[1] ("b"): invalid target"#),
+            "{error}"
+        );
+
+        let error = parse_error(
+            "targets.toml",
+            "[[targets]]\ntarget = \"a\"\ncommand_path = \"echo\"\n[[targets]]\ntarget = \"b\"\n",
+        );
+        assert!(
+            error.starts_with(r#"targets.toml: targets[1] ("b"): invalid target: either `command_path` or `url` must be specified"#),
+            "{error}"
+        );
+
+        let error = parse_error("targets.json", r#"{"targets": [{"command_path": "echo"}]}"#);
+        assert!(
+            error.starts_with("targets.json: targets[0]: invalid target"),
+            "{error}"
+        );
+
+        let error = parse_error(
+            "targets.json",
+            r#"{"targets": [{"target": "", "command_path": "echo"}]}"#,
+        );
+        assert_eq!(
+            error,
+            r#"targets.json: targets[0] (""): target name must not be empty"#
+        );
+
+        let error = parse_error(
+            "targets.json",
+            r#"{"targets": [{"target": "a", "command_path": "echo"}, {"target": "a", "url": "http://127.0.0.1/"}]}"#,
+        );
+        assert_eq!(
+            error,
+            r#"targets.json: targets[1] ("a"): duplicate target name"#
+        );
+
+        let error = parse_error(
+            "targets.json",
+            r#"{"targets": [{"target": "a", "command_path": "echo", "interval": 0}]}"#,
+        );
+        assert!(
+            error.starts_with(
+                r#"targets.json: targets[0] ("a"): invalid target: duration must be at least 1ms"#
+            ),
+            "{error}"
+        );
+
+        let error = parse_error("targets.json", r#"{"poll_intervals": 1}"#);
+        assert!(error.starts_with("invalid config targets.json"), "{error}");
+    }
+
+    #[test]
+    fn duplicate_argument_targets() {
+        let target = r#"{"target":"a","command_path":"echo"}"#;
+        let mut command = parse_args::<PollCommand>(&[target, target]).expect("valid args");
+        let error = command.merge_config(None).expect_err("duplicate");
+        assert_eq!(
+            error.message,
+            r#"targets argument 1 ("a"): duplicate target name"#
+        );
+
+        let config = PollConfig::parse(
+            Path::new("targets.json"),
+            r#"{"targets": [{"target": "a", "command_path": "date"}]}"#,
+        )
+        .expect("valid config");
+        let mut command = parse_args::<PollCommand>(&[target]).expect("valid args");
+        let error = command.merge_config(Some(config)).expect_err("duplicate");
+        assert_eq!(
+            error.message,
+            r#"target "a" is defined in both the config file and the arguments"#
+        );
+
+        let config = PollConfig::parse(
+            Path::new("targets.json"),
+            r#"{"poll_interval": 5, "targets": [{"target": "b", "command_path": "date"}]}"#,
+        )
+        .expect("valid config");
+        let mut command = parse_args::<PollCommand>(&["-i", "1", target]).expect("valid args");
+        command.merge_config(Some(config)).expect("ok");
+        let names = command
+            .targets
+            .iter()
+            .map(|t| t.target.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["b", "a"]);
+        assert_eq!(command.poll_interval.map(|t| t.get()), Some(1000));
+    }
+}
@@ -0,0 +1,384 @@
+use std::{collections::BTreeMap, io::Write, path::PathBuf};
+
+use orfail::OrFail;
+use serde::Serialize;
+
+use crate::{
+    command_view::TimeSeriesArgs,
+    ignore_broken_pipe,
+    metrics::{Number, TimeSeries},
+    num::{fmt_f64, MillisU64},
+};
+
+/// Print the summary statistics of the metrics in the results of the `poll` command.
+#[derive(Debug, clap::Args)]
+pub struct SummaryCommand {
+    /// Path to the file that contains the outputs from executing the `poll` command.
+    ///
+    /// If `-` is specified, the outputs are read from stdin until EOF.
+    metrics_jsonl_file: PathBuf,
+
+    #[clap(flatten)]
+    series: TimeSeriesArgs,
+
+    /// If specified, the statistics are calculated for each target instead of the aggregated values.
+    #[clap(long)]
+    per_target: bool,
+
+    /// Start of the time range, relative to the first record (e.g., `30`, `5m`).
+    #[clap(long)]
+    start: Option<MillisU64>,
+
+    /// End of the time range (exclusive), relative to the first record (e.g., `1h`).
+    #[clap(long)]
+    end: Option<MillisU64>,
+
+    /// Output format.
+    #[clap(long, value_enum, default_value_t = SummaryFormat::Table)]
+    format: SummaryFormat,
+
+    /// Number of decimal places when formatting floating-point values in the table.
+    #[clap(short, long, default_value_t = 3)]
+    decimal_places: u8,
+}
+
+impl SummaryCommand {
+    pub fn run(self) -> orfail::Result<()> {
+        let (ts, invalid_lines) = self.series.load(&self.metrics_jsonl_file).or_fail()?;
+        if invalid_lines > 0 {
+            eprintln!("Skipped {invalid_lines} invalid line(s)");
+        }
+        let summary = self.summarize(&ts);
+        let output = match self.format {
+            SummaryFormat::Table => self.format_table(&summary),
+            SummaryFormat::Json => serde_json::to_string_pretty(&summary).or_fail()?,
+        };
+        ignore_broken_pipe(writeln!(std::io::stdout(), "{output}"))
+    }
+
+    fn summarize(&self, ts: &TimeSeries) -> Summary {
+        let offset = |t: Option<MillisU64>| t.map(|t| ts.start_time.get().saturating_add(t.get()));
+        let start = offset(self.start).unwrap_or(ts.start_time.get());
+        let end = offset(self.end).unwrap_or(u64::MAX).min(ts.end_time.get());
+
+        let mut stats = BTreeMap::<(String, Option<String>), MetricStats>::new();
+        let segments = ts
+            .segments
+            .values()
+            .filter(|s| start <= s.start_time.get() && s.start_time.get() < end);
+        for segment in segments {
+            if self.per_target {
+                for (target, values) in &segment.target_segment_values {
+                    for (key, value) in values {
+                        // Carried values are not samples of the interval.
+                        if value.is_carried() {
+                            continue;
+                        }
+                        let Some(v) = value.value.as_number() else {
+                            continue;
+                        };
+                        stats
+                            .entry((key.clone(), Some(target.clone())))
+                            .or_default()
+                            .add(v, value.delta);
+                    }
+                }
+            } else {
+                for (key, agg_value) in &segment.aggregated_values {
+                    if !segment.has_samples(key) {
+                        continue;
+                    }
+                    let Some(v) = agg_value.value.as_ref().and_then(|v| v.as_number()) else {
+                        continue;
+                    };
+                    stats
+                        .entry((key.clone(), None))
+                        .or_default()
+                        .add(v, agg_value.delta);
+                }
+            }
+        }
+
+        Summary {
+            start_time: MillisU64::new(start).as_secs_f64(),
+            end_time: MillisU64::new(end.max(start)).as_secs_f64(),
+            metrics: stats
+                .into_iter()
+                .map(|((metric, target), stats)| stats.finish(metric, target))
+                .collect(),
+        }
+    }
+
+    fn format_table(&self, summary: &Summary) -> String {
+        let text = |v: Option<Number>| v.map(|v| v.to_text(self.decimal_places));
+        let float_text = |v: Option<f64>| v.map(|v| fmt_f64(v, self.decimal_places as usize));
+
+        let mut headers = vec!["Metric"];
+        if self.per_target {
+            headers.push("Target");
+        }
+        let text_columns = headers.len();
+        headers.extend(["Samples", "Min", "Max", "Mean", "Last", "Delta/s (mean)"]);
+
+        let mut rows = vec![headers.iter().map(|h| h.to_string()).collect::<Vec<_>>()];
+        for m in &summary.metrics {
+            let mut row = vec![m.metric.clone()];
+            if self.per_target {
+                row.push(m.target.clone().unwrap_or_default());
+            }
+            row.extend(
+                [
+                    Some(m.samples.to_string()),
+                    text(m.min),
+                    text(m.max),
+                    float_text(m.mean),
+                    text(m.last),
+                    float_text(m.mean_delta),
+                ]
+                .map(Option::unwrap_or_default),
+            );
+            rows.push(row);
+        }
+
+        let widths = (0..headers.len())
+            .map(|i| rows.iter().map(|row| row[i].chars().count()).max())
+            .map(Option::unwrap_or_default)
+            .collect::<Vec<_>>();
+        let mut lines = Vec::new();
+        for row in rows {
+            let line = row
+                .iter()
+                .zip(&widths)
+                .enumerate()
+                .map(|(i, (cell, &width))| {
+                    if i < text_columns {
+                        format!("{cell:<width$}")
+                    } else {
+                        format!("{cell:>width$}")
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("  ");
+            lines.push(line.trim_end().to_owned());
+        }
+        lines.join("\n")
+    }
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum SummaryFormat {
+    /// Aligned table for humans.
+    Table,
+
+    /// JSON object for scripts.
+    Json,
+}
+
+#[derive(Debug, Serialize)]
+struct Summary {
+    /// Unix timestamp (seconds) of the start of the summarized time range.
+    start_time: f64,
+
+    /// Unix timestamp (seconds) of the end of the summarized time range (exclusive).
+    end_time: f64,
+
+    metrics: Vec<MetricSummary>,
+}
+
+#[derive(Debug, Serialize)]
+struct MetricSummary {
+    metric: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+
+    /// Number of intervals having a numeric value.
+    ///
+    /// Intervals where the metric only has values carried forward (see `--carry-forward`) are not counted.
+    samples: usize,
+
+    min: Option<Number>,
+    max: Option<Number>,
+    mean: Option<f64>,
+    last: Option<Number>,
+
+    /// Mean of the deltas per second.
+    mean_delta: Option<f64>,
+}
+
+#[derive(Debug, Default)]
+struct MetricStats {
+    count: usize,
+    sum: f64,
+    min: Option<Number>,
+    max: Option<Number>,
+    last: Option<Number>,
+    delta_count: usize,
+    delta_sum: f64,
+}
+
+impl MetricStats {
+    fn add(&mut self, value: Number, delta: Option<Number>) {
+        self.count += 1;
+        self.sum += value.as_f64();
+        if self.min.is_none_or(|min| value < min) {
+            self.min = Some(value);
+        }
+        if self.max.is_none_or(|max| value > max) {
+            self.max = Some(value);
+        }
+        self.last = Some(value);
+        if let Some(delta) = delta {
+            self.delta_count += 1;
+            self.delta_sum += delta.as_f64();
+        }
+    }
+
+    fn finish(self, metric: String, target: Option<String>) -> MetricSummary {
+        let mean = |sum: f64, count: usize| (count > 0).then(|| sum / count as f64);
+        MetricSummary {
+            metric,
+            target,
+            samples: self.count,
+            min: self.min,
+            max: self.max,
+            mean: mean(self.sum, self.count),
+            last: self.last,
+            mean_delta: mean(self.delta_sum, self.delta_count),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse_args;
+
+    const RECORDS: &str = r#"
+{"target":"a","timestamp":0,"metrics":{"v":10,"s":"x"}}
+{"target":"a","timestamp":1,"metrics":{"v":30}}
+{"target":"a","timestamp":2,"metrics":{"v":20.5}}
+{"target":"a","timestamp":3,"metrics":{"v":60}}
+{"target":"b","timestamp":0,"metrics":{"v":1000}}
+"#;
+
+    fn summarize(records: &str, args: &[&str]) -> (SummaryCommand, Summary) {
+        let command = parse_args::<SummaryCommand>(&[&["-"], args].concat()).expect("valid args");
+        let (ts, invalid_lines) = command
+            .series
+            .read_records(records.as_bytes())
+            .expect("valid records");
+        assert_eq!(invalid_lines, 0);
+        let summary = command.summarize(&ts);
+        (command, summary)
+    }
+
+    #[test]
+    fn metric_stats() {
+        let (_, summary) = summarize(RECORDS, &["--target-filter=a"]);
+        assert_eq!(summary.start_time, 0.0);
+        assert_eq!(summary.end_time, 3.001);
+
+        // Non-numeric metrics are not summarized.
+        assert_eq!(summary.metrics.len(), 1);
+        let m = &summary.metrics[0];
+        assert_eq!(m.metric, "v");
+        assert_eq!(m.target, None);
+        assert_eq!(m.samples, 4);
+        assert_eq!(m.min, Some(Number::Integer(10)));
+        assert_eq!(m.max, Some(Number::Integer(60)));
+        assert_eq!(m.mean, Some(30.125));
+        assert_eq!(m.last, Some(Number::Integer(60)));
+        // (20 - 9.5 + 39.5) / 3
+        assert_eq!(m.mean_delta, Some(50.0 / 3.0));
+    }
+
+    #[test]
+    fn time_range() {
+        let (_, summary) = summarize(RECORDS, &["--target-filter=a", "--start=1", "--end=3"]);
+        assert_eq!(summary.start_time, 1.0);
+        assert_eq!(summary.end_time, 3.0);
+        let m = &summary.metrics[0];
+        assert_eq!(m.samples, 2);
+        assert_eq!(m.min, Some(Number::Float(20.5)));
+        assert_eq!(m.max, Some(Number::Integer(30)));
+        assert_eq!(m.last, Some(Number::Float(20.5)));
+        // The delta of the first interval is calculated from the value before the range.
+        assert_eq!(m.mean_delta, Some((20.0 - 9.5) / 2.0));
+
+        // Out of range.
+        let (_, summary) = summarize(RECORDS, &["--start=10"]);
+        assert_eq!(summary.start_time, 10.0);
+        assert_eq!(summary.end_time, 10.0);
+        assert!(summary.metrics.is_empty());
+    }
+
+    #[test]
+    fn json_output() {
+        let (_, summary) = summarize(RECORDS, &["--per-target", "--end=1"]);
+        let expected = r#"{
+  "start_time": 0.0,
+  "end_time": 1.0,
+  "metrics": [
+    {
+      "metric": "v",
+      "target": "a",
+      "samples": 1,
+      "min": 10,
+      "max": 10,
+      "mean": 10.0,
+      "last": 10,
+      "mean_delta": null
+    },
+    {
+      "metric": "v",
+      "target": "b",
+      "samples": 1,
+      "min": 1000,
+      "max": 1000,
+      "mean": 1000.0,
+      "last": 1000,
+      "mean_delta": null
+    }
+  ]
+}"#;
+        assert_eq!(
+            serde_json::to_string_pretty(&summary).expect("serialize"),
+            expected
+        );
+
+        // `target` is omitted for the aggregated values.
+        let (_, summary) = summarize(RECORDS, &["--end=1"]);
+        let json = serde_json::to_value(&summary).expect("serialize");
+        assert!(json["metrics"][0].get("target").is_none());
+    }
+
+    #[test]
+    fn table_alignment() {
+        let (command, summary) = summarize(RECORDS, &["--per-target", "--decimal-places=1"]);
+        let expected = "\
+Metric  Target  Samples    Min    Max     Mean   Last  Delta/s (mean)
+v       a             4     10     60     30.1     60            16.7
+v       b             1  1,000  1,000  1,000.0  1,000";
+        assert_eq!(command.format_table(&summary), expected);
+    }
+
+    #[test]
+    fn carried_values_are_not_samples() {
+        let records = r#"
+{"target":"a","timestamp":0,"metrics":{"v":10}}
+{"target":"a","timestamp":3,"metrics":{"v":40}}
+"#;
+        for per_target in [false, true] {
+            let mut args = vec![];
+            if per_target {
+                args.push("--per-target");
+            }
+            let (_, summary) = summarize(records, &args);
+            let m = &summary.metrics[0];
+            assert_eq!(m.samples, 2, "per_target={per_target}");
+            assert_eq!(m.mean, Some(25.0), "per_target={per_target}");
+            assert_eq!(m.last, Some(Number::Integer(40)), "per_target={per_target}");
+        }
+    }
+}
@@ -2,13 +2,18 @@ use std::path::PathBuf;
 
 use orfail::OrFail;
 
-use crate::poller::PollTarget;
+use crate::{
+    http::{HttpRequest, HttpUrl},
+    num::MillisNonZeroU64,
+    poller::{PollSource, PollTarget},
+};
 
 /// Generate a JSON object that defines a polling target.
 #[derive(Debug, clap::Args)]
 pub struct TargetCommand {
     /// Path for the command to poll the metrics of the target.
-    pub command_path: PathBuf,
+    #[clap(required_unless_present = "url", conflicts_with = "url")]
+    pub command_path: Option<PathBuf>,
 
     /// Arguments for the command.
     pub command_args: Vec<String>,
@@ -16,6 +21,32 @@ pub struct TargetCommand {
     /// The target name. If omitted, `target.${RANDOM_NUMBER}` will be used instead.
     #[clap(short, long)]
     pub name: Option<String>,
+
+    /// URL of an HTTP endpoint that returns JSON metrics (instead of executing a command).
+    #[clap(short, long)]
+    pub url: Option<HttpUrl>,
+
+    /// HTTP request method.
+    #[clap(short = 'X', long, requires = "url", conflicts_with = "command_path")]
+    pub method: Option<String>,
+
+    /// HTTP request header in the form of `NAME: VALUE` (can be specified multiple times).
+    #[clap(short = 'H', long = "header", requires = "url", conflicts_with = "command_path", value_parser = parse_header)]
+    pub headers: Vec<(String, String)>,
+
+    /// HTTP request body.
+    #[clap(short, long, requires = "url", conflicts_with = "command_path")]
+    pub body: Option<String>,
+
+    /// Polling interval for this target (e.g., `1`, `500ms`, `5m`).
+    ///
+    /// If omitted, the interval of the `poll` command is used.
+    #[clap(short, long)]
+    pub interval: Option<MillisNonZeroU64>,
+
+    /// Timeout for each poll (e.g., `10s`). A command exceeding it is killed.
+    #[clap(short, long)]
+    pub timeout: Option<MillisNonZeroU64>,
 }
 
 impl TargetCommand {
@@ -25,12 +56,76 @@ impl TargetCommand {
             .take()
             .unwrap_or_else(|| format!("target.{}", std::process::id()));
 
+        let source = if let Some(url) = self.url {
+            PollSource::Http(HttpRequest {
+                url,
+                method: self.method,
+                headers: self.headers,
+                body: self.body,
+            })
+        } else {
+            PollSource::Command {
+                command_path: self.command_path.or_fail()?,
+                command_args: self.command_args,
+            }
+        };
         let target = PollTarget {
             target,
-            command_path: self.command_path,
-            command_args: self.command_args,
+            source,
+            interval: self.interval,
+            timeout: self.timeout,
         };
         println!("{}", serde_json::to_string(&target).or_fail()?);
         Ok(())
     }
 }
+
+fn parse_header(s: &str) -> Result<(String, String), String> {
+    let (name, value) = s
+        .split_once(':')
+        .ok_or_else(|| format!("header {s:?} is not in the form of `NAME: VALUE`"))?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(format!("header {s:?} has an empty name"));
+    }
+    Ok((name.to_owned(), value.trim().to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse_args;
+
+    #[test]
+    fn http_options_require_url() {
+        for args in [
+            &["-X", "POST", "echo"][..],
+            &["-H", "X-A: 1", "echo"],
+            &["--body", "{}", "echo"],
+            &["-X", "POST"],
+        ] {
+            assert!(parse_args::<TargetCommand>(args).is_err(), "{args:?}");
+        }
+
+        let command =
+            parse_args::<TargetCommand>(&["-u", "http://127.0.0.1/", "-X", "POST", "-H", "X-A: 1"])
+                .expect("ok");
+        assert_eq!(command.method.as_deref(), Some("POST"));
+        assert_eq!(command.headers, [("X-A".to_owned(), "1".to_owned())]);
+    }
+
+    #[test]
+    fn command_or_url_is_required() {
+        assert!(parse_args::<TargetCommand>(&[]).is_err());
+        assert!(parse_args::<TargetCommand>(&["-u", "http://127.0.0.1/", "echo"]).is_err());
+        assert!(parse_args::<TargetCommand>(&["echo", "hello"]).is_ok());
+    }
+
+    #[test]
+    fn interval_must_be_positive() {
+        assert!(parse_args::<TargetCommand>(&["-i", "0", "echo"]).is_err());
+        assert!(parse_args::<TargetCommand>(&["-i", "0ms", "echo"]).is_err());
+        let command = parse_args::<TargetCommand>(&["-i", "500ms", "echo"]).expect("ok");
+        assert_eq!(command.interval.map(|t| t.get()), Some(500));
+    }
+}
@@ -1,37 +1,53 @@
-use std::path::PathBuf;
+use std::{
+    fs::File,
+    io::{IsTerminal, Read},
+    path::{Path, PathBuf},
+};
 
 use orfail::OrFail;
 use ratatui::symbols::Marker;
 use regex::Regex;
 
 use crate::{
-    jsonl::JsonlReader,
-    num::SecondsNonZeroU64,
-    viewer::{Viewer, ViewerOptions},
+    jsonl::{JsonlReader, NonBlockingReader, TailReader},
+    metrics::{AggSelector, CounterOptions, Record, SegmentAgg, TargetAgg, TimeSeries},
+    num::{MillisNonZeroU64, MillisU64},
+    viewer::{ChartMode, Viewer, ViewerOptions},
 };
 
+// Long enough for targets polled up to 10 times less frequently than the interval.
+const DEFAULT_CARRY_FORWARD_INTERVALS: u64 = 10;
+
 /// Launch the TUI viewer to visualize the results of the `poll` command.
 #[derive(Debug, clap::Args)]
 pub struct ViewCommand {
     /// Path to the file that contains the outputs from executing the `poll` command.
+    ///
+    /// The file is followed even if it is truncated or rotated (e.g., by `logrotate`).
+    /// If `-` is specified, the outputs are read from stdin (e.g., `magpies poll ... | magpies view -`).
     metrics_jsonl_file: PathBuf,
 
-    /// Time interval in seconds. Metrics within the same interval are grouped together.
-    #[clap(short, long, default_value = "1")]
-    interval: SecondsNonZeroU64,
+    #[clap(flatten)]
+    series: TimeSeriesArgs,
 
-    /// Time window in the chart in seconds.
+    /// Time window in the chart (e.g., `60`, `500ms`, `1h`).
     #[clap(short = 'w', long, default_value = "60")]
-    chart_time_window: SecondsNonZeroU64,
-
-    /// Regex pattern specifying metrics that include the visualization.
-    #[clap(short = 'f', long, default_value = ".*")]
-    metric_filter: Regex,
+    chart_time_window: MillisNonZeroU64,
 
     /// Number of decimal places when formatting floating-point values.
     #[clap(short, long, default_value_t = 3)]
     decimal_places: u8,
 
+    /// What to plot in the chart initially. This can be switched in the viewer by pressing the `c` key.
+    #[clap(long, value_enum, default_value_t = ChartMode::Delta)]
+    chart_mode: ChartMode,
+
+    /// Maximum number of targets overlaid in the chart (toggled by the `t` key).
+    ///
+    /// If there are more targets, the ones with the largest absolute values are plotted.
+    #[clap(long, default_value_t = 10, value_parser = clap::value_parser!(u16).range(1..))]
+    chart_max_targets: u16,
+
     /// If specified, the chart will be plotted using coarse-grained but highly portable characters.
     #[clap(short, long)]
     portable_chart: bool,
@@ -43,22 +59,180 @@ pub struct ViewCommand {
 
 impl ViewCommand {
     pub fn run(self) -> orfail::Result<()> {
-        let file = std::fs::File::open(&self.metrics_jsonl_file).or_fail()?;
-        let reader = JsonlReader::new(file);
+        let series = self.series;
         let options = ViewerOptions {
             absolute_time: self.absolute_time,
-            interval: self.interval,
+            interval: series.interval,
+            carry_forward: series.carry_forward(),
             chart_time_window: self.chart_time_window,
             decimal_places: self.decimal_places,
-            metric_filter: self.metric_filter,
+            segment_agg: series.segment_agg_selector(),
+            target_agg: series.target_agg_selector(),
+            counter_options: series.counter_options(),
+            metric_filter: series.metric_filter,
+            target_filter: series.target_filter,
+            chart_mode: self.chart_mode,
+            chart_max_targets: usize::from(self.chart_max_targets),
             chart_marker: if self.portable_chart {
                 Marker::Dot
             } else {
                 Marker::Braille
             },
         };
-        let app = Viewer::new(reader, options).or_fail()?;
-        app.run().or_fail()?;
+        if self.metrics_jsonl_file.as_os_str() == "-" {
+            let stdin = std::io::stdin();
+            (!stdin.is_terminal()).or_fail_with(|()| {
+                "stdin must be a pipe or a file when `-` is specified".to_owned()
+            })?;
+            let reader = JsonlReader::new(NonBlockingReader::new(stdin)).lenient(series.lenient);
+            let app = Viewer::new(reader, options).or_fail()?;
+            app.run().or_fail()?;
+        } else {
+            let file = TailReader::open(&self.metrics_jsonl_file)
+                .or_fail_with(|e| format!("{}: {e}", self.metrics_jsonl_file.display()))?;
+            let reader = JsonlReader::new(file).lenient(series.lenient);
+            let app = Viewer::new(reader, options).or_fail()?;
+            app.run().or_fail()?;
+        }
         Ok(())
     }
 }
+
+/// Options to build a [`TimeSeries`] from the outputs of the `poll` command.
+///
+/// These are shared by the commands that read the outputs (e.g., `view` and `summary`).
+#[derive(Debug, clap::Args)]
+pub struct TimeSeriesArgs {
+    /// Time interval (e.g., `1`, `250ms`, `1m`). Metrics within the same interval are grouped together.
+    #[clap(short, long, default_value = "1")]
+    pub interval: MillisNonZeroU64,
+
+    /// Maximum duration (e.g., `60`, `5m`) to carry forward the last value of a target that has no samples in an interval.
+    ///
+    /// This keeps aggregated values stable when some targets are polled less frequently than the interval.
+    /// Carried values have no Delta/s. `0` disables carrying forward.
+    ///
+    /// [default: 10 times `--interval`]
+    #[clap(long)]
+    pub carry_forward: Option<MillisU64>,
+
+    /// Regex pattern specifying metrics to include.
+    #[clap(short = 'f', long, default_value = ".*")]
+    pub metric_filter: Regex,
+
+    /// Regex pattern specifying targets to include.
+    ///
+    /// Targets can also be hidden (excluded from the aggregation) in the viewer by pressing the `h` key.
+    #[clap(short = 'T', long, default_value = ".*")]
+    pub target_filter: Regex,
+
+    /// Function to aggregate the samples of a target within an interval into a single value.
+    #[clap(long, value_enum, default_value_t = SegmentAgg::Avg)]
+    pub segment_agg: SegmentAgg,
+
+    /// Aggregation function for the metrics matching a regex pattern, in the form of `AGG=REGEX` (e.g., `max=^memory\.`).
+    ///
+    /// This can be specified multiple times. The first matching one takes precedence over `--segment-agg`.
+    #[clap(long, value_parser = parse_agg_override::<SegmentAgg>)]
+    pub segment_agg_override: Vec<(SegmentAgg, Regex)>,
+
+    /// Function to aggregate the values of the targets (e.g., in the "Aggregated Metrics" table of the viewer).
+    ///
+    /// This can also be switched in the viewer by pressing the `a` key.
+    #[clap(long, value_enum, default_value_t = TargetAgg::Sum)]
+    pub target_agg: TargetAgg,
+
+    /// Cross-target aggregation function for the metrics matching a regex pattern, in the form of `AGG=REGEX` (e.g., `avg=cpu_usage$`).
+    ///
+    /// This can be specified multiple times. The first matching one takes precedence over `--target-agg`.
+    #[clap(long, value_parser = parse_agg_override::<TargetAgg>)]
+    pub target_agg_override: Vec<(TargetAgg, Regex)>,
+
+    /// Regex pattern specifying metrics that are monotonic counters.
+    ///
+    /// When a counter decreases (e.g., the process restarted), it is regarded as a reset
+    /// and the delta is calculated as if the counter had restarted from zero.
+    #[clap(long)]
+    pub counter_filter: Option<Regex>,
+
+    /// If specified, integer metrics that have not decreased for a while are also regarded as counters.
+    #[clap(long)]
+    pub detect_counters: bool,
+
+    /// If specified, lines that are not valid JSON records (e.g., half-written ones) are skipped instead of aborting.
+    #[clap(short, long)]
+    pub lenient: bool,
+}
+
+impl TimeSeriesArgs {
+    pub fn carry_forward(&self) -> MillisU64 {
+        self.carry_forward.unwrap_or(MillisU64::new(
+            self.interval
+                .get()
+                .saturating_mul(DEFAULT_CARRY_FORWARD_INTERVALS),
+        ))
+    }
+
+    pub fn segment_agg_selector(&self) -> AggSelector<SegmentAgg> {
+        AggSelector {
+            default: self.segment_agg,
+            overrides: self.segment_agg_override.clone(),
+        }
+    }
+
+    pub fn target_agg_selector(&self) -> AggSelector<TargetAgg> {
+        AggSelector {
+            default: self.target_agg,
+            overrides: self.target_agg_override.clone(),
+        }
+    }
+
+    pub fn counter_options(&self) -> CounterOptions {
+        CounterOptions {
+            filter: self.counter_filter.clone(),
+            detect: self.detect_counters,
+        }
+    }
+
+    /// Reads all records from the file (or stdin if `-` is specified).
+    ///
+    /// Returns the synced time series and the number of the invalid lines skipped in lenient mode.
+    pub fn load(&self, path: &Path) -> orfail::Result<(TimeSeries, u64)> {
+        if path.as_os_str() == "-" {
+            self.read_records(std::io::stdin()).or_fail()
+        } else {
+            let file = File::open(path).or_fail_with(|e| format!("{}: {e}", path.display()))?;
+            self.read_records(file)
+                .or_fail_with(|e| format!("{}: {e}", path.display()))
+        }
+    }
+
+    /// Same as [`TimeSeriesArgs::load()`], but reads the records from the given reader.
+    pub fn read_records<R: Read>(&self, reader: R) -> orfail::Result<(TimeSeries, u64)> {
+        let mut ts = TimeSeries::new(
+            self.interval,
+            self.carry_forward(),
+            self.counter_options(),
+            self.segment_agg_selector(),
+            self.target_agg_selector(),
+        );
+        let mut reader = JsonlReader::new(reader).lenient(self.lenient);
+        while let Some(record) = reader.read_item::<Record>().or_fail()? {
+            if self.target_filter.is_match(&record.target) {
+                ts.insert(&record, &self.metric_filter);
+            }
+        }
+        reader.finish::<Record>().or_fail()?;
+        ts.sync_state();
+        Ok((ts, reader.invalid_line_count()))
+    }
+}
+
+fn parse_agg_override<A: clap::ValueEnum>(s: &str) -> Result<(A, Regex), String> {
+    let (agg, pattern) = s
+        .split_once('=')
+        .ok_or_else(|| format!("{s:?} is not in the form of `AGG=REGEX`"))?;
+    let agg = A::from_str(agg, false)?;
+    let pattern = Regex::new(pattern).map_err(|e| e.to_string())?;
+    Ok((agg, pattern))
+}
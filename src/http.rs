@@ -0,0 +1,407 @@
+use std::{
+    io::{Error, ErrorKind, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct HttpUrl {
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl HttpUrl {
+    fn authority(&self) -> String {
+        if self.port == 80 {
+            self.host.clone()
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+}
+
+impl FromStr for HttpUrl {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some(rest) = s.strip_prefix("http://") else {
+            return Err(format!(
+                "unsupported URL {s:?} (only `http://` is supported)"
+            ));
+        };
+
+        let (authority, path) = match rest.find(['/', '?']) {
+            Some(i) if rest[i..].starts_with('?') => (&rest[..i], format!("/{}", &rest[i..])),
+            Some(i) => (&rest[..i], rest[i..].to_owned()),
+            None => (rest, "/".to_owned()),
+        };
+
+        let (host, port) = if let Some(host) = authority.strip_prefix('[') {
+            // IPv6 literal (e.g., `[::1]:8080`).
+            let Some((host, port)) = host.split_once(']') else {
+                return Err(format!("invalid URL {s:?}"));
+            };
+            (format!("[{host}]"), port.strip_prefix(':'))
+        } else if let Some((host, port)) = authority.split_once(':') {
+            (host.to_owned(), Some(port))
+        } else {
+            (authority.to_owned(), None)
+        };
+        if host.is_empty() {
+            return Err(format!("URL {s:?} has no host"));
+        }
+
+        let port = match port {
+            None => 80,
+            Some(port) => port
+                .parse()
+                .map_err(|_| format!("URL {s:?} has an invalid port"))?,
+        };
+
+        Ok(Self { host, port, path })
+    }
+}
+
+impl TryFrom<String> for HttpUrl {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<HttpUrl> for String {
+    fn from(url: HttpUrl) -> Self {
+        url.to_string()
+    }
+}
+
+impl std::fmt::Display for HttpUrl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "http://{}{}", self.authority(), self.path)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpRequest {
+    pub url: HttpUrl,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+
+    /// Request headers as `[NAME, VALUE]` pairs (the same name can appear more than once).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub headers: Vec<(String, String)>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+}
+
+impl HttpRequest {
+    pub fn method(&self) -> &str {
+        self.method.as_deref().unwrap_or("GET")
+    }
+
+    /// Sends the request using a plain HTTP/1.1 connection and reads the whole response.
+    pub fn send(&self, timeout: Option<Duration>) -> std::io::Result<HttpResponse> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        let remaining = || -> std::io::Result<Option<Duration>> {
+            let Some(deadline) = deadline else {
+                return Ok(None);
+            };
+            let d = deadline.saturating_duration_since(Instant::now());
+            if d.is_zero() {
+                return Err(Error::new(ErrorKind::TimedOut, "request timed out"));
+            }
+            Ok(Some(d))
+        };
+
+        let mut stream = self.connect(remaining()?)?;
+        stream.set_write_timeout(remaining()?)?;
+        stream.write_all(&self.encode())?;
+
+        let mut buf = Vec::new();
+        let mut chunk = [0; 4096];
+        loop {
+            stream.set_read_timeout(remaining()?)?;
+            match stream.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => buf.extend_from_slice(&chunk[..n]),
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    return Err(Error::new(ErrorKind::TimedOut, "request timed out"));
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        HttpResponse::parse(&buf)
+    }
+
+    fn connect(&self, timeout: Option<Duration>) -> std::io::Result<TcpStream> {
+        let host = self.url.host.trim_start_matches('[').trim_end_matches(']');
+        let Some(timeout) = timeout else {
+            return TcpStream::connect((host, self.url.port));
+        };
+
+        let mut last_error = None;
+        for addr in (host, self.url.port).to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, timeout) {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error
+            .unwrap_or_else(|| Error::new(ErrorKind::NotFound, "no addresses found for the host")))
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut request = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n",
+            self.method(),
+            self.url.path,
+            self.url.authority()
+        );
+        let has_header = |name: &str| {
+            self.headers
+                .iter()
+                .any(|(k, _)| k.eq_ignore_ascii_case(name))
+        };
+        if !has_header("accept") {
+            request.push_str("Accept: application/json\r\n");
+        }
+        if let Some(body) = &self.body {
+            if !has_header("content-length") {
+                request.push_str(&format!("Content-Length: {}\r\n", body.len()));
+            }
+        }
+        for (name, value) in &self.headers {
+            request.push_str(&format!("{name}: {value}\r\n"));
+        }
+        request.push_str("\r\n");
+        if let Some(body) = &self.body {
+            request.push_str(body);
+        }
+        request.into_bytes()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    fn parse(buf: &[u8]) -> std::io::Result<Self> {
+        let invalid = |message: &str| Error::new(ErrorKind::InvalidData, message.to_owned());
+
+        let header_end = buf
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .ok_or_else(|| invalid("incomplete HTTP response header"))?;
+        let header = std::str::from_utf8(&buf[..header_end])
+            .map_err(|_| invalid("non UTF-8 HTTP response header"))?;
+        let body = &buf[header_end + 4..];
+
+        let mut lines = header.split("\r\n");
+        let status = lines
+            .next()
+            .and_then(|line| {
+                let mut tokens = line.split(' ');
+                tokens
+                    .next()
+                    .filter(|v| v.starts_with("HTTP/"))
+                    .and_then(|_| tokens.next())
+                    .and_then(|s| s.parse().ok())
+            })
+            .ok_or_else(|| invalid("invalid HTTP status line"))?;
+
+        let mut chunked = false;
+        let mut content_length = None;
+        for line in lines {
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("transfer-encoding") {
+                chunked = value.eq_ignore_ascii_case("chunked");
+            } else if name.eq_ignore_ascii_case("content-length") {
+                content_length = Some(
+                    value
+                        .parse::<usize>()
+                        .map_err(|_| invalid("invalid Content-Length header"))?,
+                );
+            }
+        }
+
+        let body = if chunked {
+            decode_chunked(body).ok_or_else(|| invalid("malformed chunked HTTP body"))?
+        } else if let Some(n) = content_length {
+            (n <= body.len())
+                .then(|| body[..n].to_vec())
+                .ok_or_else(|| invalid("truncated HTTP body"))?
+        } else {
+            body.to_vec()
+        };
+
+        Ok(Self { status, body })
+    }
+}
+
+fn decode_chunked(mut buf: &[u8]) -> Option<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = buf.windows(2).position(|w| w == b"\r\n")?;
+        let size = std::str::from_utf8(&buf[..line_end]).ok()?;
+        let size = size.split(';').next()?.trim();
+        let size = usize::from_str_radix(size, 16).ok()?;
+        buf = &buf[line_end + 2..];
+        if size == 0 {
+            return Some(body);
+        }
+        body.extend_from_slice(buf.get(..size)?);
+        buf = buf.get(size + 2..)?;
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{io::BufRead, net::TcpListener};
+
+    use super::*;
+
+    /// Starts a server that reads one request and replies with `response`.
+    /// The returned handle yields the raw request head.
+    pub(crate) fn serve_once(
+        response: &'static [u8],
+    ) -> (HttpUrl, std::thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+        let port = listener.local_addr().expect("local_addr").port();
+        let handle = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().expect("accept");
+            let mut reader = std::io::BufReader::new(stream);
+            let mut head = String::new();
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).expect("read") == 0 || line == "\r\n" {
+                    break;
+                }
+                head.push_str(&line);
+            }
+            reader.get_mut().write_all(response).expect("write");
+            head
+        });
+        let url = format!("http://127.0.0.1:{port}/stats")
+            .parse()
+            .expect("url");
+        (url, handle)
+    }
+
+    fn request(url: HttpUrl) -> HttpRequest {
+        HttpRequest {
+            url,
+            method: None,
+            headers: Vec::new(),
+            body: None,
+        }
+    }
+
+    #[test]
+    fn parse_url() {
+        let url: HttpUrl = "http://example.com".parse().expect("valid");
+        assert_eq!(url.host, "example.com");
+        assert_eq!(url.port, 80);
+        assert_eq!(url.path, "/");
+
+        let url: HttpUrl = "http://127.0.0.1:8080/stats?full=1".parse().expect("valid");
+        assert_eq!(url.host, "127.0.0.1");
+        assert_eq!(url.port, 8080);
+        assert_eq!(url.path, "/stats?full=1");
+        assert_eq!(url.to_string(), "http://127.0.0.1:8080/stats?full=1");
+
+        let url: HttpUrl = "http://[::1]:9000?x".parse().expect("valid");
+        assert_eq!(url.host, "[::1]");
+        assert_eq!(url.port, 9000);
+        assert_eq!(url.path, "/?x");
+    }
+
+    #[test]
+    fn parse_bad_url() {
+        assert!("https://example.com".parse::<HttpUrl>().is_err());
+        assert!("example.com".parse::<HttpUrl>().is_err());
+        assert!("http://".parse::<HttpUrl>().is_err());
+        assert!("http://:80/".parse::<HttpUrl>().is_err());
+        assert!("http://example.com:http/".parse::<HttpUrl>().is_err());
+        assert!("http://example.com:65536/".parse::<HttpUrl>().is_err());
+        assert!("http://[::1/".parse::<HttpUrl>().is_err());
+    }
+
+    #[test]
+    fn parse_content_length_response() {
+        let response =
+            HttpResponse::parse(b"HTTP/1.1 200 OK\r\nContent-Length: 7\r\n\r\n{\"a\":1}trailing")
+                .expect("valid");
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, b"{\"a\":1}");
+
+        assert!(HttpResponse::parse(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n{}").is_err());
+        assert!(HttpResponse::parse(b"HTTP/1.1 200 OK\r\n").is_err());
+        assert!(HttpResponse::parse(b"SSH-2.0\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn parse_chunked_response() {
+        let response = HttpResponse::parse(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3;ext=1\r\n{\"a\r\n4\r\n\":1}\r\n0\r\n\r\n",
+        )
+        .expect("valid");
+        assert_eq!(response.body, b"{\"a\":1}");
+
+        assert!(HttpResponse::parse(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nA\r\n{}\r\n"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn send_request() {
+        let (url, server) = serve_once(b"HTTP/1.1 200 OK\r\nContent-Length: 8\r\n\r\n{\"a\":10}");
+        let mut request = request(url);
+        request.headers = vec![
+            ("X-Tag".to_owned(), "foo".to_owned()),
+            ("X-Tag".to_owned(), "bar".to_owned()),
+        ];
+        let response = request.send(Some(Duration::from_secs(5))).expect("ok");
+        assert!(response.is_success());
+        assert_eq!(response.body, b"{\"a\":10}");
+
+        let head = server.join().expect("server");
+        assert!(head.starts_with("GET /stats HTTP/1.1\r\n"));
+        assert!(head.contains("X-Tag: foo\r\n"));
+        assert!(head.contains("X-Tag: bar\r\n"));
+    }
+
+    #[test]
+    fn send_request_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+        let port = listener.local_addr().expect("local_addr").port();
+        let url = format!("http://127.0.0.1:{port}/").parse().expect("url");
+
+        // The connection is accepted by the OS backlog, but no response is ever sent.
+        let error = request(url)
+            .send(Some(Duration::from_millis(100)))
+            .expect_err("timeout");
+        assert_eq!(error.kind(), ErrorKind::TimedOut);
+        drop(listener);
+    }
+}
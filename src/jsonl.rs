@@ -1,4 +1,9 @@
-use std::io::Read;
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::mpsc,
+};
 
 use orfail::OrFail;
 use serde::Deserialize;
@@ -9,6 +14,13 @@ pub struct JsonlReader<R> {
     buf: Vec<u8>,
     buf_offset: usize,
     buf_end: usize,
+    line_number: u64,
+    lenient: bool,
+    invalid_line_count: u64,
+    last_invalid_line: Option<u64>,
+
+    // Whether the partial line at `buf_offset` has already been returned as an item.
+    partial_line_read: bool,
 }
 
 impl<R: Read> JsonlReader<R> {
@@ -18,51 +30,566 @@ impl<R: Read> JsonlReader<R> {
             buf: vec![0; 4096],
             buf_offset: 0,
             buf_end: 0,
+            line_number: 0,
+            lenient: false,
+            invalid_line_count: 0,
+            last_invalid_line: None,
+            partial_line_read: false,
         }
     }
 
+    /// If `true`, lines that are not valid JSON items are skipped instead of resulting in an error.
+    ///
+    /// The number of the skipped lines can be obtained via [`JsonlReader::invalid_line_count()`].
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Returns the number of the lines skipped in lenient mode.
+    pub fn invalid_line_count(&self) -> u64 {
+        self.invalid_line_count
+    }
+
+    /// Returns the 1-origin number of the last line skipped in lenient mode.
+    pub fn last_invalid_line(&self) -> Option<u64> {
+        self.last_invalid_line
+    }
+
+    /// Reads the next item.
+    ///
+    /// `Ok(None)` is returned when the inner reader reaches EOF, but more items can be read
+    /// later if the inner reader is still being appended (e.g., a file being written by `magpies poll`).
     pub fn read_item<T>(&mut self) -> orfail::Result<Option<T>>
     where
         T: for<'de> Deserialize<'de>,
     {
-        if self.buf_offset != 0 {
+        loop {
             if let Some(i) = self.buf[self.buf_offset..self.buf_end]
                 .iter()
                 .position(|&b| b == b'\n')
-                .map(|i| self.buf_offset + i)
             {
-                let item = serde_json::from_slice(&self.buf[self.buf_offset..i]).or_fail()?;
-                self.buf_offset = i + 1;
-                return Ok(item);
+                let start = self.buf_offset;
+                self.buf_offset += i + 1;
+                self.line_number += 1;
+                if std::mem::take(&mut self.partial_line_read) {
+                    // The line has been completed by the newline, but its item was already returned.
+                    continue;
+                }
+                if let Some(item) = self.parse_line(start, start + i).or_fail()? {
+                    return Ok(Some(item));
+                }
+                continue;
             }
 
-            self.buf.copy_within(self.buf_offset..self.buf_end, 0);
-            self.buf_end -= self.buf_offset;
-            self.buf_offset = 0;
-        }
-
-        loop {
+            if self.buf_offset != 0 {
+                self.buf.copy_within(self.buf_offset..self.buf_end, 0);
+                self.buf_end -= self.buf_offset;
+                self.buf_offset = 0;
+            }
             if self.buf_end == self.buf.len() {
                 self.buf.resize(self.buf.len() * 2, 0);
             }
 
             let read_size = self.inner.read(&mut self.buf[self.buf_end..]).or_fail()?;
             if read_size == 0 {
-                return Ok(None);
+                return Ok(self.read_partial_line());
             }
-
-            let old_end = self.buf_end;
             self.buf_end += read_size;
+        }
+    }
 
-            if let Some(i) = self.buf[old_end..self.buf_end]
-                .iter()
-                .position(|&b| b == b'\n')
-                .map(|i| old_end + i)
-            {
-                let item = serde_json::from_slice(&self.buf[..i]).or_fail()?;
-                self.buf_offset = i + 1;
-                return Ok(Some(item));
+    /// Handles the remaining partial line (i.e., a line without a trailing newline) as the last line.
+    ///
+    /// This should be called after [`JsonlReader::read_item()`] returns `Ok(None)`
+    /// if the inner reader has really reached the end of the stream.
+    pub fn finish<T>(&mut self) -> orfail::Result<()>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        let (start, end) = (self.buf_offset, self.buf_end);
+        self.buf_offset = self.buf_end;
+        if start == end {
+            return Ok(());
+        }
+
+        self.line_number += 1;
+        if !std::mem::take(&mut self.partial_line_read) {
+            self.parse_line::<T>(start, end).or_fail()?;
+        }
+        Ok(())
+    }
+
+    // Returns the partial line at EOF if it is a complete JSON item.
+    //
+    // As the line may still be being written, it is kept buffered (and not counted)
+    // until the trailing newline arrives.
+    fn read_partial_line<T>(&mut self) -> Option<T>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        if self.partial_line_read {
+            return None;
+        }
+        let line = trim_line(&self.buf[self.buf_offset..self.buf_end]);
+        if line.iter().all(|b| b.is_ascii_whitespace()) {
+            return None;
+        }
+        let item = serde_json::from_slice(line).ok()?;
+        self.partial_line_read = true;
+        Some(item)
+    }
+
+    fn parse_line<T>(&mut self, start: usize, end: usize) -> orfail::Result<Option<T>>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        let line = trim_line(&self.buf[start..end]);
+        if line.iter().all(|b| b.is_ascii_whitespace()) {
+            return Ok(None);
+        }
+        match serde_json::from_slice(line) {
+            Ok(item) => Ok(Some(item)),
+            Err(_) if self.lenient => {
+                self.invalid_line_count += 1;
+                self.last_invalid_line = Some(self.line_number);
+                Ok(None)
+            }
+            Err(e) => Err(orfail::Failure::new(format!(
+                "invalid JSON at line {}: {e}",
+                self.line_number
+            ))),
+        }
+    }
+}
+
+impl<R: Read + Rewind> JsonlReader<R> {
+    /// Returns `true` if the items can be read again from the beginning via [`JsonlReader::rewind()`].
+    pub fn can_rewind(&self) -> bool {
+        self.inner.can_rewind()
+    }
+
+    /// Restarts reading from the beginning of the inner reader (the invalid line count is also reset).
+    pub fn rewind(&mut self) -> std::io::Result<()> {
+        self.inner.rewind()?;
+        self.buf_offset = 0;
+        self.buf_end = 0;
+        self.line_number = 0;
+        self.invalid_line_count = 0;
+        self.last_invalid_line = None;
+        self.partial_line_read = false;
+        Ok(())
+    }
+}
+
+/// Reader that can restart reading from the beginning of its source.
+pub trait Rewind {
+    /// Returns `false` if the source cannot be read again (e.g., stdin).
+    fn can_rewind(&self) -> bool;
+
+    fn rewind(&mut self) -> std::io::Result<()>;
+}
+
+// A file truncated by `copytruncate` while the writer keeps its offset
+// is padded with NUL bytes up to the position of the next line.
+fn trim_line(line: &[u8]) -> &[u8] {
+    &line[line.iter().take_while(|&&b| b == 0).count()..]
+}
+
+/// [`Read`] implementation that reads the inner reader (e.g., stdin) in a background thread.
+///
+/// `read()` never blocks and returns `Ok(0)` if no data has arrived yet,
+/// which is the same as reading a file that is still being appended.
+#[derive(Debug)]
+pub struct NonBlockingReader {
+    rx: mpsc::Receiver<std::io::Result<Vec<u8>>>,
+    buf: Vec<u8>,
+    buf_offset: usize,
+}
+
+impl NonBlockingReader {
+    pub fn new<R>(mut inner: R) -> Self
+    where
+        R: 'static + Send + Read,
+    {
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut buf = vec![0; 4096];
+            loop {
+                let result = inner.read(&mut buf).map(|n| buf[..n].to_vec());
+                let done = !matches!(&result, Ok(data) if !data.is_empty());
+                if tx.send(result).is_err() || done {
+                    break;
+                }
+            }
+        });
+        Self {
+            rx,
+            buf: Vec::new(),
+            buf_offset: 0,
+        }
+    }
+}
+
+impl Rewind for NonBlockingReader {
+    fn can_rewind(&self) -> bool {
+        false
+    }
+
+    fn rewind(&mut self) -> std::io::Result<()> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "cannot rewind a stream",
+        ))
+    }
+}
+
+impl Read for NonBlockingReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.buf_offset == self.buf.len() {
+            match self.rx.try_recv() {
+                Ok(data) => {
+                    self.buf = data?;
+                    self.buf_offset = 0;
+                }
+                Err(_) => return Ok(0),
+            }
+        }
+
+        let n = buf.len().min(self.buf.len() - self.buf_offset);
+        buf[..n].copy_from_slice(&self.buf[self.buf_offset..][..n]);
+        self.buf_offset += n;
+        Ok(n)
+    }
+}
+
+/// [`Read`] implementation that follows a file being appended, like `tail -F`.
+///
+/// When the end of the file is reached, the path is checked for truncation (the size became
+/// smaller than the read offset or the beginning of the file changed) and rotation
+/// (the path refers to another file).
+/// In either case, reading restarts from the beginning of the current file at the path.
+/// Before that, the unread remainder of the old file is read: from the renamed file itself in the case
+/// of rotation, and from `${PATH}.1` if the file was truncated after being copied there (i.e., `copytruncate`).
+///
+/// A newline is inserted when switching files so that a partial line is not joined with the next one.
+#[derive(Debug)]
+pub struct TailReader {
+    path: PathBuf,
+    file: File,
+    file_id: Option<(u64, u64)>,
+    offset: u64,
+    head: Vec<u8>,
+    last_byte: Option<u8>,
+    pending: Vec<u8>,
+}
+
+impl TailReader {
+    const HEAD_SIZE: usize = 1024;
+
+    pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = File::open(&path)?;
+        let file_id = file_id(&file.metadata()?);
+        Ok(Self {
+            path,
+            file,
+            file_id,
+            offset: 0,
+            head: Vec::new(),
+            last_byte: None,
+            pending: Vec::new(),
+        })
+    }
+
+    // Returns `Ok(true)` if the file at the path has been switched.
+    fn reopen_if_changed(&mut self) -> std::io::Result<bool> {
+        let Ok(metadata) = std::fs::metadata(&self.path) else {
+            // The file may be being rotated.
+            return Ok(false);
+        };
+
+        if file_id(&metadata) != self.file_id {
+            // Rotated: the old file may have been appended after it was renamed.
+            let Ok(file) = File::open(&self.path) else {
+                return Ok(false);
+            };
+            let mut old_file = std::mem::replace(&mut self.file, file);
+            old_file.read_to_end(&mut self.pending)?;
+            self.file_id = file_id(&self.file.metadata()?);
+        } else if metadata.len() < self.offset || self.is_head_changed()? {
+            // Truncated (and possibly rewritten beyond the previous offset).
+            self.pending = self.read_rotated_copy_remainder();
+            self.file.seek(SeekFrom::Start(0))?;
+        } else {
+            return Ok(false);
+        }
+
+        // The remainder of the old file continues the line read so far,
+        // but the new file must not be joined with the last line of the old one.
+        if self
+            .pending
+            .last()
+            .or(self.last_byte.as_ref())
+            .is_some_and(|&b| b != b'\n')
+        {
+            self.pending.push(b'\n');
+        }
+        self.offset = 0;
+        self.head.clear();
+        Ok(true)
+    }
+
+    fn is_head_changed(&mut self) -> std::io::Result<bool> {
+        let mut head = vec![0; self.head.len()];
+        self.file.seek(SeekFrom::Start(0))?;
+        let result = self.file.read_exact(&mut head);
+        self.file.seek(SeekFrom::Start(self.offset))?;
+        Ok(result.is_err() || head != self.head)
+    }
+
+    fn read_rotated_copy_remainder(&self) -> Vec<u8> {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".1");
+        let Ok(mut file) = File::open(path) else {
+            return Vec::new();
+        };
+
+        // Makes sure that the copy has the same content as the one read so far.
+        let mut head = vec![0; self.head.len()];
+        if file.read_exact(&mut head).is_err() || head != self.head {
+            return Vec::new();
+        }
+
+        let mut remainder = Vec::new();
+        if file.seek(SeekFrom::Start(self.offset)).is_err()
+            || file.read_to_end(&mut remainder).is_err()
+        {
+            return Vec::new();
+        }
+        remainder
+    }
+}
+
+impl Rewind for TailReader {
+    fn can_rewind(&self) -> bool {
+        true
+    }
+
+    // Reads the current file at the path from the beginning (the rotated files are not read).
+    fn rewind(&mut self) -> std::io::Result<()> {
+        let file = File::open(&self.path)?;
+        self.file_id = file_id(&file.metadata()?);
+        self.file = file;
+        self.offset = 0;
+        self.head.clear();
+        self.last_byte = None;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+impl Read for TailReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            if !self.pending.is_empty() {
+                let n = buf.len().min(self.pending.len());
+                buf[..n].copy_from_slice(&self.pending[..n]);
+                self.pending.drain(..n);
+                self.last_byte = Some(buf[n - 1]);
+                return Ok(n);
+            }
+
+            let n = self.file.read(buf)?;
+            if n > 0 {
+                let head_len = self.head.len();
+                if head_len < Self::HEAD_SIZE && self.offset == head_len as u64 {
+                    let m = n.min(Self::HEAD_SIZE - head_len);
+                    self.head.extend_from_slice(&buf[..m]);
+                }
+                self.offset += n as u64;
+                self.last_byte = Some(buf[n - 1]);
+                return Ok(n);
+            }
+
+            if !self.reopen_if_changed()? {
+                return Ok(0);
             }
         }
     }
 }
+
+#[cfg(unix)]
+fn file_id(metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+    use super::*;
+    use crate::test_util::TempDir;
+
+    /// Reader whose data can be appended after it reaches EOF.
+    #[derive(Debug, Default, Clone)]
+    struct GrowingReader(Rc<RefCell<VecDeque<u8>>>);
+
+    impl GrowingReader {
+        fn append(&self, data: &str) {
+            self.0.borrow_mut().extend(data.as_bytes());
+        }
+    }
+
+    impl Read for GrowingReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().read(buf)
+        }
+    }
+
+    fn read_all(reader: &mut JsonlReader<impl Read>) -> orfail::Result<Vec<u32>> {
+        let mut items = Vec::new();
+        while let Some(item) = reader.read_item::<serde_json::Value>()? {
+            items.push(item["v"].as_u64().expect("number") as u32);
+        }
+        Ok(items)
+    }
+
+    fn append_file(path: &Path, data: &str) {
+        use std::io::Write;
+
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut f| f.write_all(data.as_bytes()))
+            .expect("append");
+    }
+
+    #[test]
+    fn tail_truncated_file() {
+        let dir = TempDir::new("jsonl-truncate");
+        let path = dir.path().join("metrics.jsonl");
+        append_file(&path, "{\"v\":1}\n{\"v\":2}\n");
+        let mut reader = JsonlReader::new(TailReader::open(&path).expect("open"));
+        assert_eq!(read_all(&mut reader).expect("ok"), [1, 2]);
+
+        std::fs::write(&path, "{\"v\":3}\n").expect("write");
+        assert_eq!(read_all(&mut reader).expect("ok"), [3]);
+
+        // Rewritten with the same size.
+        std::fs::write(&path, "{\"v\":4}\n").expect("write");
+        assert_eq!(read_all(&mut reader).expect("ok"), [4]);
+    }
+
+    #[test]
+    fn tail_copytruncate_with_partial_line() {
+        let dir = TempDir::new("jsonl-copytruncate");
+        let path = dir.path().join("metrics.jsonl");
+        append_file(&path, "{\"v\":1}\n{\"v\":");
+        let mut reader = JsonlReader::new(TailReader::open(&path).expect("open"));
+        assert_eq!(read_all(&mut reader).expect("ok"), [1]);
+
+        // The rest of the line is written, then copied to `metrics.jsonl.1` before the reader reads it.
+        append_file(&path, "2}\n");
+        std::fs::copy(&path, path.with_extension("jsonl.1")).expect("copy");
+        std::fs::write(&path, "{\"v\":3}\n").expect("truncate");
+        assert_eq!(read_all(&mut reader).expect("ok"), [2, 3]);
+    }
+
+    #[test]
+    fn tail_renamed_file() {
+        let dir = TempDir::new("jsonl-rename");
+        let path = dir.path().join("metrics.jsonl");
+        let rotated_path = path.with_extension("jsonl.1");
+        append_file(&path, "{\"v\":1}\n");
+        let mut reader = JsonlReader::new(TailReader::open(&path).expect("open"));
+        assert_eq!(read_all(&mut reader).expect("ok"), [1]);
+
+        // The writer keeps appending to the renamed file until it reopens the path.
+        std::fs::rename(&path, &rotated_path).expect("rename");
+        append_file(&rotated_path, "{\"v\":2}\n{\"v\":");
+        append_file(&path, "{\"v\":3}\n");
+
+        let mut reader = reader.lenient(true);
+        assert_eq!(read_all(&mut reader).expect("ok"), [2, 3]);
+        assert_eq!(reader.last_invalid_line(), Some(3));
+    }
+
+    #[test]
+    fn rewind_tailed_file() {
+        let dir = TempDir::new("jsonl-rewind");
+        let path = dir.path().join("metrics.jsonl");
+        append_file(&path, "{\"v\":1}\nfoo\n{\"v\":2}\n");
+        let mut reader = JsonlReader::new(TailReader::open(&path).expect("open")).lenient(true);
+        assert!(reader.can_rewind());
+        assert_eq!(read_all(&mut reader).expect("ok"), [1, 2]);
+
+        append_file(&path, "{\"v\":3}\n");
+        reader.rewind().expect("rewind");
+        assert_eq!(reader.invalid_line_count(), 0);
+        assert_eq!(read_all(&mut reader).expect("ok"), [1, 2, 3]);
+        assert_eq!(reader.invalid_line_count(), 1);
+        assert_eq!(reader.last_invalid_line(), Some(2));
+
+        let reader = JsonlReader::new(NonBlockingReader::new(std::io::empty()));
+        assert!(!reader.can_rewind());
+    }
+
+    #[test]
+    fn corrupt_middle_line() {
+        let data = "{\"v\":1}\n{\"v\":\n\n{\"v\":3}\n";
+
+        let mut reader = JsonlReader::new(data.as_bytes());
+        let error = read_all(&mut reader).expect_err("error");
+        assert!(error.message.contains("invalid JSON at line 2"));
+
+        let mut reader = JsonlReader::new(data.as_bytes()).lenient(true);
+        assert_eq!(read_all(&mut reader).expect("ok"), [1, 3]);
+        assert_eq!(reader.invalid_line_count(), 1);
+        assert_eq!(reader.last_invalid_line(), Some(2));
+    }
+
+    #[test]
+    fn trailing_partial_line() {
+        let mut reader = JsonlReader::new("{\"v\":1}\n{\"v\":2}".as_bytes());
+        assert_eq!(read_all(&mut reader).expect("ok"), [1, 2]);
+        reader.finish::<serde_json::Value>().expect("ok");
+
+        // An incomplete final line is an error only when the stream has ended.
+        let mut reader = JsonlReader::new("{\"v\":1}\n{\"v\":".as_bytes());
+        assert_eq!(read_all(&mut reader).expect("ok"), [1]);
+        let error = reader.finish::<serde_json::Value>().expect_err("error");
+        assert!(error.message.contains("invalid JSON at line 2"));
+
+        let mut reader = JsonlReader::new("{\"v\":1}\n{\"v\":".as_bytes()).lenient(true);
+        assert_eq!(read_all(&mut reader).expect("ok"), [1]);
+        reader.finish::<serde_json::Value>().expect("ok");
+        assert_eq!(reader.last_invalid_line(), Some(2));
+    }
+
+    #[test]
+    fn partial_line_completed_later() {
+        let inner = GrowingReader::default();
+        let mut reader = JsonlReader::new(inner.clone()).lenient(true);
+
+        inner.append("{\"v\":1}\n{\"v\":2}");
+        assert_eq!(read_all(&mut reader).expect("ok"), [1, 2]);
+        assert!(read_all(&mut reader).expect("ok").is_empty());
+
+        inner.append("\nfoo\n{\"v\":");
+        assert!(read_all(&mut reader).expect("ok").is_empty());
+        assert_eq!(reader.last_invalid_line(), Some(3));
+
+        inner.append("4}\nbar\n");
+        assert_eq!(read_all(&mut reader).expect("ok"), [4]);
+        assert_eq!(reader.invalid_line_count(), 2);
+        assert_eq!(reader.last_invalid_line(), Some(5));
+    }
+}
@@ -1,8 +1,24 @@
+pub mod command_export;
 pub mod command_poll;
+pub mod command_summary;
 pub mod command_target;
 pub mod command_view;
+pub mod http;
 pub mod jsonl;
 pub mod metrics;
 pub mod num;
 pub mod poller;
 pub mod viewer;
+
+/// Regards a write error caused by the reader side of stdout having exited (e.g., `head`) as success.
+pub(crate) fn ignore_broken_pipe(result: std::io::Result<()>) -> orfail::Result<()> {
+    use orfail::OrFail;
+
+    match result {
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        result => result.or_fail(),
+    }
+}
+
+#[cfg(test)]
+mod test_util;
@@ -1,6 +1,7 @@
 use clap::Parser;
 use magpies::{
-    command_poll::PollCommand, command_target::TargetCommand, command_view::ViewCommand,
+    command_export::ExportCommand, command_poll::PollCommand, command_summary::SummaryCommand,
+    command_target::TargetCommand, command_view::ViewCommand,
 };
 use orfail::OrFail;
 
@@ -10,6 +11,8 @@ use orfail::OrFail;
 enum Args {
     Poll(PollCommand),
     View(ViewCommand),
+    Summary(SummaryCommand),
+    Export(ExportCommand),
     Target(TargetCommand),
 }
 
@@ -18,6 +21,8 @@ fn main() -> orfail::Result<()> {
     match args {
         Args::Poll(c) => c.run().or_fail()?,
         Args::View(c) => c.run().or_fail()?,
+        Args::Summary(c) => c.run().or_fail()?,
+        Args::Export(c) => c.run().or_fail()?,
         Args::Target(c) => c.run().or_fail()?,
     }
     Ok(())
@@ -1,19 +1,28 @@
 use std::{
     cmp::Ordering,
     collections::{BTreeMap, BTreeSet},
+    num::NonZeroU64,
     time::Duration,
 };
 
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 
-use crate::num::{fmt_f64, fmt_i64, SecondsF64, SecondsNonZeroU64, SecondsU64};
+use crate::{
+    num::{fmt_f64, fmt_i128, MillisNonZeroU64, MillisU64, SecondsF64},
+    poller::PollError,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Record {
     pub target: String,
     pub timestamp: SecondsF64,
+
+    #[serde(default)]
     pub metrics: serde_json::Value,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<PollError>,
 }
 
 impl Record {
@@ -40,7 +49,8 @@ pub struct FlattenedRecord {
 pub enum MetricValue {
     Null,
     Bool(bool),
-    Integer(i64),
+    // `i128` can hold both `i64` and `u64` values as they are.
+    Integer(i128),
     Float(f64),
     String(String),
 }
@@ -54,7 +64,7 @@ impl MetricValue {
         matches!(self, Self::Integer(_))
     }
 
-    pub fn as_i64(&self) -> Option<i64> {
+    pub fn as_i128(&self) -> Option<i128> {
         if let Self::Integer(v) = self {
             Some(*v)
         } else {
@@ -62,6 +72,14 @@ impl MetricValue {
         }
     }
 
+    pub fn as_number(&self) -> Option<Number> {
+        match self {
+            Self::Integer(v) => Some(Number::Integer(*v)),
+            Self::Float(v) => Some(Number::Float(*v)),
+            _ => None,
+        }
+    }
+
     pub fn as_f64(&self) -> Option<f64> {
         if let Self::Integer(v) = self {
             Some(*v as f64)
@@ -121,9 +139,9 @@ fn flatten_json_value(
         }
         serde_json::Value::Number(v) => {
             if let Some(v) = v.as_i64() {
-                metrics.insert(key.clone(), MetricValue::Integer(v));
-            } else if v.as_u64().is_some() {
-                metrics.insert(key.clone(), MetricValue::Integer(i64::MAX));
+                metrics.insert(key.clone(), MetricValue::Integer(i128::from(v)));
+            } else if let Some(v) = v.as_u64() {
+                metrics.insert(key.clone(), MetricValue::Integer(i128::from(v)));
             } else if let Some(v) = v.as_f64() {
                 metrics.insert(key.clone(), MetricValue::Float(v));
             } else {
@@ -161,21 +179,51 @@ fn flatten_json_value(
 
 #[derive(Debug, Clone)]
 pub struct TimeSeries {
-    pub start_time: SecondsU64,
-    pub end_time: SecondsU64,
-    pub segment_duration: SecondsNonZeroU64,
-    pub segments: BTreeMap<SecondsU64, TimeSeriesSegment>,
-    pub dirty_segments: BTreeSet<SecondsU64>,
+    pub start_time: MillisU64,
+
+    /// Exclusive end of the time range (i.e., the timestamp of the latest record plus 1ms).
+    pub end_time: MillisU64,
+
+    pub segment_duration: MillisNonZeroU64,
+    pub segments: BTreeMap<MillisU64, TimeSeriesSegment>,
+    pub dirty_segments: BTreeSet<MillisU64>,
+    pub error_counts: BTreeMap<String, u64>,
+
+    /// Maximum duration to carry forward the last value of a target that has no samples in a segment.
+    pub carry_forward: MillisU64,
+
+    pub counter_options: CounterOptions,
+
+    /// Function to aggregate the samples of a target within a segment into a single value.
+    pub segment_agg: AggSelector<SegmentAgg>,
+
+    /// Function to aggregate the values of the targets within a segment.
+    pub target_agg: AggSelector<TargetAgg>,
+
+    /// Targets excluded from the aggregated values.
+    pub hidden_targets: BTreeSet<String>,
 }
 
 impl TimeSeries {
-    pub fn new(segment_duration: SecondsNonZeroU64) -> Self {
+    pub fn new(
+        segment_duration: MillisNonZeroU64,
+        carry_forward: MillisU64,
+        counter_options: CounterOptions,
+        segment_agg: AggSelector<SegmentAgg>,
+        target_agg: AggSelector<TargetAgg>,
+    ) -> Self {
         Self {
-            start_time: SecondsU64::new(0),
-            end_time: SecondsU64::new(0),
+            start_time: MillisU64::new(0),
+            end_time: MillisU64::new(0),
             segment_duration,
+            carry_forward,
+            counter_options,
+            segment_agg,
+            target_agg,
+            hidden_targets: BTreeSet::new(),
             segments: BTreeMap::new(),
             dirty_segments: BTreeSet::new(),
+            error_counts: BTreeMap::new(),
         }
     }
 
@@ -183,14 +231,21 @@ impl TimeSeries {
         self.segments.is_empty()
     }
 
-    pub fn insert(&mut self, record: &Record, filter: &Regex) {
-        let mut record = record.flatten();
-        record.metrics.retain(|k, _| filter.is_match(k));
+    /// Removes all inserted records while keeping the options.
+    pub fn clear(&mut self) {
+        self.start_time = MillisU64::new(0);
+        self.end_time = MillisU64::new(0);
+        self.segments.clear();
+        self.dirty_segments.clear();
+        self.error_counts.clear();
+    }
 
-        let start_time = record.timestamp.as_secs();
-        self.end_time = self.end_time.max(SecondsU64::new(start_time + 1));
+    pub fn insert(&mut self, record: &Record, filter: &Regex) {
+        let timestamp = record.timestamp.to_duration().as_millis() as u64;
+        let start_time = timestamp - timestamp % self.segment_duration.get();
+        self.end_time = self.end_time.max(MillisU64::new(timestamp + 1));
 
-        let start_time = SecondsU64::new(start_time - start_time % self.segment_duration.get());
+        let start_time = MillisU64::new(start_time);
         if self.segments.is_empty() || start_time < self.start_time {
             self.start_time = start_time;
         }
@@ -203,7 +258,21 @@ impl TimeSeries {
                 segment_duration: self.segment_duration,
                 aggregated_values: BTreeMap::new(),
                 target_segment_values: BTreeMap::new(),
+                error_counts: BTreeMap::new(),
             });
+        self.dirty_segments.insert(start_time);
+
+        if record.error.is_some() {
+            *segment
+                .error_counts
+                .entry(record.target.clone())
+                .or_default() += 1;
+            *self.error_counts.entry(record.target.clone()).or_default() += 1;
+            return;
+        }
+
+        let mut record = record.flatten();
+        record.metrics.retain(|k, _| filter.is_match(k));
         let target_segment = segment
             .target_segment_values
             .entry(record.target)
@@ -215,30 +284,33 @@ impl TimeSeries {
                 .raw_values
                 .push(value);
         }
-
-        self.dirty_segments.insert(start_time);
     }
 
-    pub fn last_start_time(&self) -> SecondsU64 {
+    pub fn last_start_time(&self) -> MillisU64 {
         self.segments
             .last_key_value()
             .map(|x| *x.0)
             .unwrap_or_default()
     }
 
+    /// Re-calculates all segments (e.g., after changing aggregation functions).
+    pub fn resync_state(&mut self) {
+        self.dirty_segments.extend(self.segments.keys().copied());
+        self.sync_state();
+    }
+
     pub fn sync_state(&mut self) {
         let empty_segment = TimeSeriesSegment::empty(self.segment_duration);
         for start_time in std::mem::take(&mut self.dirty_segments) {
-            let prev_time = start_time
-                .get()
-                .checked_sub(self.segment_duration.get())
-                .map(SecondsU64::new);
-            let prev_segment = prev_time
-                .and_then(|t| self.segments.get(&t))
+            let prev_segment = self
+                .segments
+                .range(..start_time)
+                .next_back()
+                .map(|x| x.1)
                 .unwrap_or(&empty_segment);
 
             let mut segment = self.segments.get(&start_time).expect("unreachable").clone();
-            segment.sync_state(prev_segment);
+            segment.sync_state(prev_segment, self);
             self.segments.insert(start_time, segment);
         }
     }
@@ -246,247 +318,848 @@ impl TimeSeries {
 
 #[derive(Debug, Clone)]
 pub struct TimeSeriesSegment {
-    pub start_time: SecondsU64,
-    pub segment_duration: SecondsNonZeroU64,
+    pub start_time: MillisU64,
+    pub segment_duration: MillisNonZeroU64,
     pub aggregated_values: BTreeMap<String, AggregatedValue>,
     pub target_segment_values: BTreeMap<String, BTreeMap<String, SegmentValue>>,
+    pub error_counts: BTreeMap<String, u64>,
 }
 
 impl TimeSeriesSegment {
-    pub fn empty(segment_duration: SecondsNonZeroU64) -> Self {
+    pub fn empty(segment_duration: MillisNonZeroU64) -> Self {
         Self {
-            start_time: SecondsU64::new(0),
+            start_time: MillisU64::new(0),
             segment_duration,
             aggregated_values: BTreeMap::new(),
             target_segment_values: BTreeMap::new(),
+            error_counts: BTreeMap::new(),
         }
     }
 
-    pub fn end_time(&self) -> SecondsU64 {
-        SecondsU64::new(self.start_time.get() + self.segment_duration.get())
+    pub fn end_time(&self) -> MillisU64 {
+        MillisU64::new(self.start_time.get() + self.segment_duration.get())
     }
 
-    fn sync_state(&mut self, prev_segment: &Self) {
-        self.sync_target_segment_values(prev_segment);
-        self.sync_aggregated_values(prev_segment);
+    /// Returns `true` if some target has a value of the metric actually sampled (i.e., not carried forward) in this segment.
+    pub fn has_samples(&self, key: &str) -> bool {
+        self.target_segment_values
+            .values()
+            .any(|values| values.get(key).is_some_and(|v| !v.is_carried()))
     }
 
-    fn sync_target_segment_values(&mut self, prev_segment: &Self) {
+    fn sync_state(&mut self, prev_segment: &Self, ts: &TimeSeries) {
+        self.sync_target_segment_values(prev_segment, &ts.counter_options, &ts.segment_agg);
+        self.carry_forward_values(prev_segment, ts.carry_forward);
+        self.sync_aggregated_values(prev_segment, &ts.target_agg, &ts.hidden_targets);
+    }
+
+    fn sync_target_segment_values(
+        &mut self,
+        prev_segment: &Self,
+        counter_options: &CounterOptions,
+        segment_agg: &AggSelector<SegmentAgg>,
+    ) {
         for (target, segment_values) in &mut self.target_segment_values {
+            // Values carried forward by the previous sync are recalculated later.
+            segment_values.retain(|_, v| !v.raw_values.is_empty());
+
             for (key, segment_value) in segment_values {
-                segment_value.sync_representative_value();
+                segment_value.carried_from = None;
+                segment_value.delta = None;
+                segment_value.reset = false;
+                segment_value.monotonic_steps = 0;
+                segment_value.sync_representative_value(segment_agg.select(key));
                 if let Some(prev_segment_value) = prev_segment
                     .target_segment_values
                     .get(target)
                     .and_then(|v| v.get(key))
                 {
-                    segment_value.sync_delta(prev_segment_value, self.segment_duration);
+                    let sampled_at = prev_segment_value.sampled_at(prev_segment.start_time);
+                    if let Some(elapsed) = self
+                        .start_time
+                        .get()
+                        .checked_sub(sampled_at.get())
+                        .and_then(NonZeroU64::new)
+                    {
+                        let is_counter = counter_options.is_counter(key, prev_segment_value);
+                        segment_value.sync_delta(
+                            prev_segment_value,
+                            MillisNonZeroU64::new(elapsed),
+                            is_counter,
+                        );
+                    }
                 }
             }
         }
     }
 
-    fn sync_aggregated_values(&mut self, prev_segment: &Self) {
-        let keys = self
-            .target_segment_values
-            .values()
+    // Targets polled at longer intervals than the segment duration have no samples in
+    // some segments, so their last values are carried forward to keep aggregations stable.
+    fn carry_forward_values(&mut self, prev_segment: &Self, carry_forward: MillisU64) {
+        for (target, prev_values) in &prev_segment.target_segment_values {
+            for (key, prev_value) in prev_values {
+                let sampled_at = prev_value.sampled_at(prev_segment.start_time);
+                if self.start_time.get().saturating_sub(sampled_at.get()) > carry_forward.get() {
+                    continue;
+                }
+
+                let values = self
+                    .target_segment_values
+                    .entry(target.clone())
+                    .or_default();
+                if values.contains_key(key) {
+                    continue;
+                }
+                values.insert(
+                    key.clone(),
+                    SegmentValue {
+                        value: prev_value.value.clone(),
+                        // A carried value has not changed, so the previous delta would be stale.
+                        delta: None,
+                        raw_values: Vec::new(),
+                        carried_from: Some(sampled_at),
+                        reset: false,
+                        monotonic_steps: prev_value.monotonic_steps,
+                    },
+                );
+            }
+        }
+    }
+
+    fn sync_aggregated_values(
+        &mut self,
+        prev_segment: &Self,
+        target_agg: &AggSelector<TargetAgg>,
+        hidden_targets: &BTreeSet<String>,
+    ) {
+        self.aggregated_values.clear();
+        let visible_values = || {
+            self.target_segment_values
+                .iter()
+                .filter(|(target, _)| !hidden_targets.contains(*target))
+                .map(|(_, segment_values)| segment_values)
+        };
+        let keys = visible_values()
             .flat_map(|segment_values| segment_values.keys())
             .collect::<BTreeSet<_>>();
         for key in keys {
-            let mut sum = None;
-            for value in self
-                .target_segment_values
-                .values()
-                .filter_map(|segment_values| segment_values.get(key).map(|v| &v.value))
-            {
-                match (value, sum) {
-                    (value, None) => {
-                        sum = Some(value.clone());
-                    }
-                    (RepresentativeValue::Avg(_), Some(RepresentativeValue::Set(_))) => {
-                        sum = None;
-                        break;
-                    }
-                    (RepresentativeValue::Set(_), Some(RepresentativeValue::Avg(_))) => {
-                        sum = None;
-                        break;
-                    }
-                    (RepresentativeValue::Avg(a), Some(RepresentativeValue::Avg(b))) => {
-                        if let Some(v) = number_add(a.clone(), b.clone()) {
-                            sum = Some(RepresentativeValue::Avg(v));
-                        } else {
-                            sum = None;
-                            break;
-                        }
-                    }
-                    (RepresentativeValue::Set(a), Some(RepresentativeValue::Set(mut b))) => {
-                        b.extend(a.iter().cloned());
-                        sum = Some(RepresentativeValue::Set(b));
-                    }
-                }
-            }
+            let agg = target_agg.select(key);
+            let target_values = visible_values()
+                .filter_map(|segment_values| segment_values.get(key))
+                .collect::<Vec<_>>();
+            let value = agg.apply(target_values.iter().map(|v| &v.value));
 
             let mut delta = None;
-            if let Some(RepresentativeValue::Avg(v0)) = prev_segment
+            let delta_agg = match agg {
+                TargetAgg::Sum => Some(SegmentAgg::Sum),
+                TargetAgg::Avg => Some(SegmentAgg::Avg),
+                _ => None,
+            };
+            if let (Some(delta_agg), true) = (delta_agg, target_values.iter().any(|v| v.reset)) {
+                // The difference of the aggregated values is meaningless if some counters have been reset.
+                let mut deltas = target_values
+                    .iter()
+                    .filter_map(|v| v.delta)
+                    .collect::<Vec<_>>();
+                delta = delta_agg.apply(&mut deltas);
+            } else if let Some(RepresentativeValue::Number(v0)) = prev_segment
                 .aggregated_values
                 .get(key)
-                .and_then(|v| v.sum.as_ref())
+                .and_then(|v| v.value.as_ref())
             {
-                if let Some(RepresentativeValue::Avg(v1)) = &sum {
-                    delta = number_delta(v1.clone(), v0.clone(), self.segment_duration);
+                let elapsed = self
+                    .start_time
+                    .get()
+                    .checked_sub(prev_segment.start_time.get())
+                    .and_then(NonZeroU64::new);
+                if let (Some(RepresentativeValue::Number(v1)), Some(elapsed)) = (&value, elapsed) {
+                    delta = number_delta(*v1, *v0, MillisNonZeroU64::new(elapsed));
                 }
             }
-            self.aggregated_values
-                .insert(key.clone(), AggregatedValue { sum, delta });
+            let distribution = Distribution::new(target_values.iter().map(|v| &v.value));
+            self.aggregated_values.insert(
+                key.clone(),
+                AggregatedValue {
+                    value,
+                    delta,
+                    distribution,
+                },
+            );
         }
     }
 }
 
-fn number_delta(
-    a: serde_json::Number,
-    b: serde_json::Number,
-    d: SecondsNonZeroU64,
-) -> Option<serde_json::Number> {
-    let d = d.get();
-    apply_number_op(a, b, |a, b| (a - b) / d as i64, |a, b| (a - b) / d as f64)
+/// Numeric representation of metric values and their deltas.
+///
+/// Integers are kept exactly as long as they fit in `i128`, otherwise they fall back to `f64`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Number {
+    Integer(i128),
+    Float(f64),
+}
+
+impl Number {
+    /// Makes a float number. Returns `None` if `v` is not finite (like [`serde_json::Number::from_f64()`]).
+    pub fn from_f64(v: f64) -> Option<Self> {
+        v.is_finite().then_some(Self::Float(v))
+    }
+
+    pub fn as_i128(self) -> Option<i128> {
+        if let Self::Integer(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_f64(self) -> f64 {
+        match self {
+            Self::Integer(v) => v as f64,
+            Self::Float(v) => v,
+        }
+    }
+
+    pub fn to_text(self, decimal_places: u8) -> String {
+        match self {
+            Self::Integer(v) => fmt_i128(v),
+            Self::Float(v) => fmt_f64(v, decimal_places as usize),
+        }
+    }
+}
+
+impl PartialOrd for Number {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Self::Integer(a), Self::Integer(b)) => a.partial_cmp(b),
+            _ => self.as_f64().partial_cmp(&other.as_f64()),
+        }
+    }
+}
+
+/// Options to determine which metrics are monotonic counters.
+///
+/// When the value of a counter decreases, it is regarded as a reset (e.g., the process restarted)
+/// and the delta is calculated assuming that the counter restarted from zero.
+#[derive(Debug, Clone, Default)]
+pub struct CounterOptions {
+    /// Metrics whose names match this pattern are always treated as counters.
+    pub filter: Option<Regex>,
+
+    /// If `true`, integer metrics that have not decreased for a while are also treated as counters.
+    pub detect: bool,
+}
+
+impl CounterOptions {
+    /// Number of consecutive non-decreasing changes required to regard a metric as a counter heuristically.
+    pub const DETECTION_STEPS: u64 = 5;
+
+    fn is_counter(&self, key: &str, prev: &SegmentValue) -> bool {
+        if self
+            .filter
+            .as_ref()
+            .is_some_and(|filter| filter.is_match(key))
+        {
+            return true;
+        }
+        self.detect
+            && prev.monotonic_steps >= Self::DETECTION_STEPS
+            && matches!(prev.value, RepresentativeValue::Number(Number::Integer(v)) if v >= 0)
+    }
+}
+
+/// Function to aggregate the samples of a target within a segment.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SegmentAgg {
+    #[default]
+    Avg,
+    Min,
+    Max,
+    Last,
+    First,
+    Sum,
+    Median,
+}
+
+impl SegmentAgg {
+    // Returns `None` if `values` is empty or the result is not a finite number.
+    fn apply(self, values: &mut [Number]) -> Option<Number> {
+        let first = *values.first()?;
+        match self {
+            Self::Avg => {
+                let sum = values
+                    .iter()
+                    .skip(1)
+                    .try_fold(first, |a, b| number_add(a, *b))?;
+                number_div(sum, values.len())
+            }
+            Self::Min => Some(
+                values
+                    .iter()
+                    .fold(first, |a, b| if *b < a { *b } else { a }),
+            ),
+            Self::Max => Some(
+                values
+                    .iter()
+                    .fold(first, |a, b| if *b > a { *b } else { a }),
+            ),
+            Self::Last => values.last().copied(),
+            Self::First => Some(first),
+            Self::Sum => values
+                .iter()
+                .skip(1)
+                .try_fold(first, |a, b| number_add(a, *b)),
+            Self::Median => {
+                values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+                let mid = values.len() / 2;
+                if values.len() % 2 == 1 {
+                    Some(values[mid])
+                } else {
+                    number_div(number_add(values[mid - 1], values[mid])?, 2)
+                }
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for SegmentAgg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Avg => "avg",
+            Self::Min => "min",
+            Self::Max => "max",
+            Self::Last => "last",
+            Self::First => "first",
+            Self::Sum => "sum",
+            Self::Median => "median",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Function to aggregate the values of the targets within a segment.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TargetAgg {
+    #[default]
+    Sum,
+    Avg,
+    Min,
+    Max,
+    Count,
+}
+
+impl TargetAgg {
+    /// Returns the next function (used to cycle through the functions in the viewer).
+    pub fn next(self) -> Self {
+        match self {
+            Self::Sum => Self::Avg,
+            Self::Avg => Self::Min,
+            Self::Min => Self::Max,
+            Self::Max => Self::Count,
+            Self::Count => Self::Sum,
+        }
+    }
+
+    // Numeric values are aggregated by the function and non-numeric ones are merged into a set.
+    // Returns `None` if numeric and non-numeric values are mixed.
+    fn apply<'a, I>(self, values: I) -> Option<RepresentativeValue>
+    where
+        I: Iterator<Item = &'a RepresentativeValue>,
+    {
+        let agg = match self {
+            Self::Sum => SegmentAgg::Sum,
+            Self::Avg => SegmentAgg::Avg,
+            Self::Min => SegmentAgg::Min,
+            Self::Max => SegmentAgg::Max,
+            Self::Count => {
+                let count = values.count() as i128;
+                return Some(RepresentativeValue::Number(Number::Integer(count)));
+            }
+        };
+
+        let mut numbers = Vec::new();
+        let mut set = BTreeSet::new();
+        for value in values {
+            match value {
+                RepresentativeValue::Number(v) => numbers.push(*v),
+                RepresentativeValue::Set(vs) => set.extend(vs.iter().cloned()),
+            }
+        }
+        match (numbers.is_empty(), set.is_empty()) {
+            (false, true) => agg.apply(&mut numbers).map(RepresentativeValue::Number),
+            (true, _) => Some(RepresentativeValue::Set(set)),
+            (false, false) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for TargetAgg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Sum => "sum",
+            Self::Avg => "avg",
+            Self::Min => "min",
+            Self::Max => "max",
+            Self::Count => "count",
+        };
+        write!(f, "{name}")
+    }
 }
 
-fn number_add(a: serde_json::Number, b: serde_json::Number) -> Option<serde_json::Number> {
-    apply_number_op(a, b, |a, b| a + b, |a, b| a + b)
+/// Selects an aggregation function for each metric.
+#[derive(Debug, Clone, Default)]
+pub struct AggSelector<A> {
+    pub default: A,
+
+    /// Pairs of an aggregation function and a pattern of the metrics to which it applies.
+    ///
+    /// The first matching pair takes precedence over the default.
+    pub overrides: Vec<(A, Regex)>,
 }
 
-fn apply_number_op<F0, F1>(
-    a: serde_json::Number,
-    b: serde_json::Number,
-    f0: F0,
-    f1: F1,
-) -> Option<serde_json::Number>
-where
-    F0: FnOnce(i64, i64) -> i64,
-    F1: FnOnce(f64, f64) -> f64,
-{
-    if let (Some(v0), Some(v1)) = (a.as_i64(), b.as_i64()) {
-        Some(serde_json::Number::from(f0(v0, v1)))
-    } else if let (Some(v0), Some(v1)) = (a.as_f64(), b.as_f64()) {
-        serde_json::Number::from_f64(f1(v0, v1))
-    } else {
-        None
+impl<A: Copy> AggSelector<A> {
+    pub fn select(&self, key: &str) -> A {
+        self.overrides
+            .iter()
+            .find(|(_, pattern)| pattern.is_match(key))
+            .map_or(self.default, |(agg, _)| *agg)
     }
 }
 
+impl<A: std::fmt::Display> std::fmt::Display for AggSelector<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.default)?;
+        if !self.overrides.is_empty() {
+            let overrides = self
+                .overrides
+                .iter()
+                .map(|(agg, pattern)| format!("{agg}={pattern}"))
+                .collect::<Vec<_>>();
+            write!(f, " ({})", overrides.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+fn number_div(a: Number, n: usize) -> Option<Number> {
+    match a {
+        Number::Integer(v) => Some(Number::Integer(v / n as i128)),
+        Number::Float(v) => Number::from_f64(v / n as f64),
+    }
+}
+
+fn number_delta(a: Number, b: Number, d: MillisNonZeroU64) -> Option<Number> {
+    let d = d.get();
+    if let (Number::Integer(a), Number::Integer(b)) = (a, b) {
+        if let Some(v) = a.checked_sub(b).and_then(|v| v.checked_mul(1000)) {
+            return Some(Number::Integer(v / i128::from(d)));
+        }
+    }
+    Number::from_f64((a.as_f64() - b.as_f64()) * 1000.0 / d as f64)
+}
+
+fn number_add(a: Number, b: Number) -> Option<Number> {
+    if let (Number::Integer(a), Number::Integer(b)) = (a, b) {
+        if let Some(v) = a.checked_add(b) {
+            return Some(Number::Integer(v));
+        }
+    }
+    Number::from_f64(a.as_f64() + b.as_f64())
+}
+
 #[derive(Debug, Clone)]
 pub struct AggregatedValue {
-    pub sum: Option<RepresentativeValue>,
-    pub delta: Option<serde_json::Number>,
+    pub value: Option<RepresentativeValue>,
+    pub delta: Option<Number>,
+
+    /// Distribution of the values of the targets (only for numeric metrics).
+    pub distribution: Option<Distribution>,
 }
 
 impl AggregatedValue {
-    pub fn sum_text(&self, decimal_places: u8) -> String {
-        let Some(v) = &self.sum else {
+    pub fn value_text(&self, decimal_places: u8) -> String {
+        let Some(v) = &self.value else {
             return "".to_owned();
         };
         match v {
-            RepresentativeValue::Avg(v) => {
-                if let Some(v) = v.as_i64() {
-                    fmt_i64(v)
-                } else if let Some(v) = v.as_f64() {
-                    fmt_f64(v, decimal_places as usize)
-                } else {
-                    unreachable!()
-                }
-            }
+            RepresentativeValue::Number(v) => v.to_text(decimal_places),
             RepresentativeValue::Set(vs) => serde_json::to_string(vs).expect("unreachable"),
         }
     }
 
     pub fn delta_text(&self, decimal_places: u8) -> String {
-        let Some(v) = &self.delta else {
-            return "".to_owned();
-        };
-        if let Some(v) = v.as_i64() {
-            fmt_i64(v)
-        } else if let Some(v) = v.as_f64() {
-            fmt_f64(v, decimal_places as usize)
-        } else {
-            unreachable!()
+        self.delta
+            .map(|v| v.to_text(decimal_places))
+            .unwrap_or_default()
+    }
+}
+
+/// Statistics of the values of the targets within a segment.
+#[derive(Debug, Clone)]
+pub struct Distribution {
+    pub min: Number,
+    pub p50: Number,
+    pub p90: Number,
+    pub p99: Number,
+    pub max: Number,
+
+    /// Population standard deviation.
+    pub stddev: Number,
+}
+
+impl Distribution {
+    // Returns `None` if there are no values or some of them are not numeric.
+    fn new<'a, I>(values: I) -> Option<Self>
+    where
+        I: Iterator<Item = &'a RepresentativeValue>,
+    {
+        let mut numbers = values
+            .map(|v| match v {
+                RepresentativeValue::Number(v) => Some(*v),
+                RepresentativeValue::Set(_) => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+        if numbers.is_empty() {
+            return None;
         }
+        numbers.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+
+        // Nearest-rank method.
+        let percentile = |p: usize| {
+            let rank = (p * numbers.len()).div_ceil(100).max(1);
+            numbers[rank - 1]
+        };
+
+        let n = numbers.len() as f64;
+        let mean = numbers.iter().map(|v| v.as_f64()).sum::<f64>() / n;
+        let variance = numbers
+            .iter()
+            .map(|v| (v.as_f64() - mean).powi(2))
+            .sum::<f64>()
+            / n;
+
+        Some(Self {
+            min: numbers[0],
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            max: numbers[numbers.len() - 1],
+            stddev: Number::from_f64(variance.sqrt())?,
+        })
+    }
+
+    pub fn texts(&self, decimal_places: u8) -> [String; 6] {
+        [
+            self.min,
+            self.p50,
+            self.p90,
+            self.p99,
+            self.max,
+            self.stddev,
+        ]
+        .map(|v| v.to_text(decimal_places))
     }
 }
 
 #[derive(Debug, Default, Clone)]
 pub struct SegmentValue {
     pub value: RepresentativeValue,
-    pub delta: Option<serde_json::Number>,
+    pub delta: Option<Number>,
     pub raw_values: Vec<MetricValue>,
+
+    /// Start time of the segment where the value was actually sampled if the value has been carried forward.
+    pub carried_from: Option<MillisU64>,
+
+    /// Whether the value is a counter that has been reset (i.e., decreased) since the previous sample.
+    pub reset: bool,
+
+    /// Number of consecutive non-decreasing changes up to this value.
+    pub monotonic_steps: u64,
 }
 
 impl SegmentValue {
-    fn sync_representative_value(&mut self) {
-        if self.raw_values.iter().all(|v| v.is_integer()) {
-            let sum: i64 = self.raw_values.iter().filter_map(|v| v.as_i64()).sum();
-            let avg = sum / self.raw_values.len() as i64;
-            self.value = RepresentativeValue::Avg(serde_json::Number::from(avg));
+    pub fn is_carried(&self) -> bool {
+        self.carried_from.is_some()
+    }
+
+    fn sampled_at(&self, segment_start_time: MillisU64) -> MillisU64 {
+        self.carried_from.unwrap_or(segment_start_time)
+    }
+
+    fn sync_representative_value(&mut self, agg: SegmentAgg) {
+        let numbers = self
+            .raw_values
+            .iter()
+            .map(|v| v.as_number())
+            .collect::<Option<Vec<_>>>();
+        if let Some(v) = numbers.and_then(|mut vs| agg.apply(&mut vs)) {
+            self.value = RepresentativeValue::Number(v);
             return;
-        } else if self.raw_values.iter().all(|v| v.is_number()) {
-            let sum: f64 = self.raw_values.iter().filter_map(|v| v.as_f64()).sum();
-            let avg = sum / self.raw_values.len() as f64;
-            if let Some(v) = serde_json::Number::from_f64(avg) {
-                self.value = RepresentativeValue::Avg(v);
-                return;
-            }
         }
 
-        self.value = RepresentativeValue::Set(self.raw_values.iter().cloned().collect());
+        let values = match agg {
+            SegmentAgg::First => &self.raw_values[..1],
+            SegmentAgg::Last => &self.raw_values[self.raw_values.len() - 1..],
+            _ => &self.raw_values[..],
+        };
+        self.value = RepresentativeValue::Set(values.iter().cloned().collect());
     }
 
-    fn sync_delta(&mut self, prev: &Self, segment_duration: SecondsNonZeroU64) {
-        let RepresentativeValue::Avg(v0) = &self.value else {
+    fn sync_delta(&mut self, prev: &Self, segment_duration: MillisNonZeroU64, is_counter: bool) {
+        let RepresentativeValue::Number(v0) = &self.value else {
             return;
         };
-        let RepresentativeValue::Avg(v1) = &prev.value else {
+        let RepresentativeValue::Number(v1) = &prev.value else {
             return;
         };
 
-        self.delta = number_delta(v0.clone(), v1.clone(), segment_duration);
+        if v0 >= v1 {
+            self.monotonic_steps = prev.monotonic_steps + 1;
+            self.delta = number_delta(*v0, *v1, segment_duration);
+        } else if is_counter {
+            // Like Prometheus' `rate()`, a reset counter is assumed to have restarted from zero.
+            self.reset = true;
+            self.monotonic_steps = prev.monotonic_steps + 1;
+            self.delta = number_delta(*v0, Number::Integer(0), segment_duration);
+        } else {
+            self.delta = number_delta(*v0, *v1, segment_duration);
+        }
     }
 
     pub fn value_text(&self, decimal_places: u8) -> String {
         match &self.value {
-            RepresentativeValue::Avg(v) => {
-                if let Some(v) = v.as_i64() {
-                    fmt_i64(v)
-                } else if let Some(v) = v.as_f64() {
-                    fmt_f64(v, decimal_places as usize)
-                } else {
-                    unreachable!()
-                }
-            }
+            RepresentativeValue::Number(v) => v.to_text(decimal_places),
             RepresentativeValue::Set(vs) => serde_json::to_string(vs).expect("unreachable"),
         }
     }
 
     pub fn delta_text(&self, decimal_places: u8) -> String {
-        let Some(v) = &self.delta else {
-            return "".to_owned();
-        };
-        if let Some(v) = v.as_i64() {
-            fmt_i64(v)
-        } else if let Some(v) = v.as_f64() {
-            fmt_f64(v, decimal_places as usize)
-        } else {
-            unreachable!()
-        }
+        self.delta
+            .map(|v| v.to_text(decimal_places))
+            .unwrap_or_default()
     }
 }
 
 #[derive(Debug, Clone)]
 pub enum RepresentativeValue {
-    Avg(serde_json::Number),
+    Number(Number),
     Set(BTreeSet<MetricValue>),
 }
 
+impl RepresentativeValue {
+    pub fn as_number(&self) -> Option<Number> {
+        if let Self::Number(v) = self {
+            Some(*v)
+        } else {
+            None
+        }
+    }
+}
+
 impl Default for RepresentativeValue {
     fn default() -> Self {
         Self::Set(BTreeSet::new())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(target: &str, timestamp: f64, metrics: serde_json::Value) -> Record {
+        let record =
+            serde_json::json!({"target": target, "timestamp": timestamp, "metrics": metrics});
+        serde_json::from_value(record).expect("valid record")
+    }
+
+    fn time_series(interval: u64, carry_forward: u64, records: &[Record]) -> TimeSeries {
+        let mut ts = TimeSeries::new(
+            MillisNonZeroU64::new(NonZeroU64::new(interval).expect("non-zero")),
+            MillisU64::new(carry_forward),
+            CounterOptions::default(),
+            AggSelector::default(),
+            AggSelector::default(),
+        );
+        let filter = Regex::new(".*").expect("valid regex");
+        for record in records {
+            ts.insert(record, &filter);
+        }
+        ts.sync_state();
+        ts
+    }
+
+    fn segment_value<'a>(
+        ts: &'a TimeSeries,
+        start: u64,
+        target: &str,
+        key: &str,
+    ) -> Option<&'a SegmentValue> {
+        ts.segments
+            .get(&MillisU64::new(start))?
+            .target_segment_values
+            .get(target)?
+            .get(key)
+    }
+
+    #[test]
+    fn time_range() {
+        let records = [
+            record("a", 10.25, serde_json::json!({})),
+            record("a", 12.5, serde_json::json!({})),
+            record("a", 11.0, serde_json::json!({})),
+        ];
+        let ts = time_series(1000, 0, &records);
+        assert_eq!(ts.start_time, MillisU64::new(10_000));
+        assert_eq!(ts.end_time, MillisU64::new(12_501));
+
+        let ts = time_series(250, 0, &records);
+        assert_eq!(ts.start_time, MillisU64::new(10_250));
+        assert_eq!(ts.end_time, MillisU64::new(12_501));
+    }
+
+    #[test]
+    fn carry_forward_values() {
+        let records = [
+            record("a", 0.0, serde_json::json!({"v": 10})),
+            record("a", 1.0, serde_json::json!({"v": 20})),
+            record("a", 4.0, serde_json::json!({"v": 50})),
+            // Only to create the segments in between.
+            record("b", 2.0, serde_json::json!({})),
+            record("b", 3.0, serde_json::json!({})),
+        ];
+
+        // Disabled.
+        let ts = time_series(1000, 0, &records);
+        assert!(segment_value(&ts, 2000, "a", "v").is_none());
+
+        let ts = time_series(1000, 5000, &records);
+        let v = segment_value(&ts, 1000, "a", "v").expect("sampled");
+        assert_eq!(v.delta, Some(Number::Integer(10)));
+        for start in [2000, 3000] {
+            let v = segment_value(&ts, start, "a", "v").expect("carried");
+            assert_eq!(v.carried_from, Some(MillisU64::new(1000)));
+            assert_eq!(v.value.as_number(), Some(Number::Integer(20)));
+            assert_eq!(v.delta, None);
+        }
+
+        // The delta of the next sample is calculated from the actual sampled time.
+        let v = segment_value(&ts, 4000, "a", "v").expect("sampled");
+        assert!(!v.is_carried());
+        assert_eq!(v.delta, Some(Number::Integer(10)));
+    }
+
+    #[test]
+    fn flatten_large_integers() {
+        let metrics = serde_json::from_str(
+            r#"{"u": 18446744073709551615, "i": -9223372036854775808, "big": 18446744073709551616}"#,
+        )
+        .expect("valid JSON");
+        let record = record("a", 0.0, metrics).flatten();
+        assert_eq!(
+            record.metrics["u"],
+            MetricValue::Integer(i128::from(u64::MAX))
+        );
+        assert_eq!(
+            record.metrics["i"],
+            MetricValue::Integer(i128::from(i64::MIN))
+        );
+        // Beyond `u64::MAX`, `serde_json` only provides the value as `f64`.
+        assert!(matches!(record.metrics["big"], MetricValue::Float(_)));
+    }
+
+    #[test]
+    fn large_integer_deltas() {
+        let d = |millis| MillisNonZeroU64::new(NonZeroU64::new(millis).expect("non-zero"));
+        let max = i128::from(u64::MAX);
+
+        let delta = number_delta(Number::Integer(max), Number::Integer(max - 3000), d(1000));
+        assert_eq!(delta, Some(Number::Integer(3000)));
+        let delta = number_delta(Number::Integer(max - 3000), Number::Integer(max), d(2000));
+        assert_eq!(delta, Some(Number::Integer(-1500)));
+        let delta = number_delta(Number::Integer(max), Number::Integer(0), d(1000));
+        assert_eq!(delta, Some(Number::Integer(max)));
+
+        // The sum of large counters exceeds `u64::MAX` without losing precision.
+        let sum = number_add(Number::Integer(max), Number::Integer(max));
+        assert_eq!(sum, Some(Number::Integer(max * 2)));
+
+        // Falls back to `f64` on `i128` overflow.
+        let delta = number_delta(Number::Integer(i128::MAX), Number::Integer(0), d(1000));
+        assert!(matches!(delta, Some(Number::Float(_))));
+    }
+
+    #[test]
+    fn large_counter_wraparound() {
+        let near_max = u64::MAX - 1000;
+        let records = [
+            record("a", 0.0, serde_json::json!({"c": near_max - 2000})),
+            record("a", 1.0, serde_json::json!({"c": near_max})),
+            // Wrapped around (or reset).
+            record("a", 2.0, serde_json::json!({"c": 500})),
+            record("a", 3.0, serde_json::json!({"c": 700})),
+        ];
+
+        // Without counter options, the wraparound is just a large decrease.
+        let mut ts = time_series(1000, 0, &records);
+        let v = segment_value(&ts, 1000, "a", "c").expect("value");
+        assert_eq!(v.delta, Some(Number::Integer(2000)));
+        let v = segment_value(&ts, 2000, "a", "c").expect("value");
+        assert!(!v.reset);
+        assert_eq!(v.delta, Some(Number::Integer(500 - i128::from(near_max))));
+
+        // As a counter, the wraparound is regarded as a reset to zero (like Prometheus' `rate()`).
+        ts.counter_options.filter = Some(Regex::new("^c$").expect("valid regex"));
+        ts.resync_state();
+        let v = segment_value(&ts, 2000, "a", "c").expect("value");
+        assert!(v.reset);
+        assert_eq!(v.delta, Some(Number::Integer(500)));
+        let aggregated = &ts.segments[&MillisU64::new(2000)].aggregated_values["c"];
+        assert_eq!(aggregated.delta, Some(Number::Integer(500)));
+        let v = segment_value(&ts, 3000, "a", "c").expect("value");
+        assert!(!v.reset);
+        assert_eq!(v.delta, Some(Number::Integer(200)));
+
+        // Detected heuristically only after enough non-decreasing steps.
+        let mut records = (0..=CounterOptions::DETECTION_STEPS)
+            .map(|i| record("a", i as f64, serde_json::json!({"c": near_max + i})))
+            .collect::<Vec<_>>();
+        let wrapped_at = CounterOptions::DETECTION_STEPS + 1;
+        records.push(record("a", wrapped_at as f64, serde_json::json!({"c": 10})));
+        let mut ts = time_series(1000, 0, &records);
+        ts.counter_options.detect = true;
+        ts.resync_state();
+        let v = segment_value(&ts, wrapped_at * 1000, "a", "c").expect("value");
+        assert!(v.reset);
+        assert_eq!(v.delta, Some(Number::Integer(10)));
+    }
+
+    #[test]
+    fn format_large_integers() {
+        let max = i128::from(u64::MAX);
+        assert_eq!(
+            Number::Integer(max).to_text(3),
+            "18,446,744,073,709,551,615"
+        );
+        assert_eq!(
+            Number::Integer(max * 2).to_text(3),
+            "36,893,488,147,419,103,230"
+        );
+        assert_eq!(
+            Number::Integer(-max).to_text(3),
+            "-18,446,744,073,709,551,615"
+        );
+        assert_eq!(crate::num::fmt_u64(u64::MAX), "18,446,744,073,709,551,615");
+        assert_eq!(
+            crate::num::fmt_i128(i128::MIN),
+            "-170,141,183,460,469,231,731,687,303,715,884,105,728"
+        );
+
+        let value = SegmentValue {
+            value: RepresentativeValue::Number(Number::Integer(max)),
+            delta: Some(Number::Integer(-max)),
+            ..Default::default()
+        };
+        assert_eq!(value.value_text(3), "18,446,744,073,709,551,615");
+        assert_eq!(value.delta_text(3), "-18,446,744,073,709,551,615");
+    }
+}
@@ -1,5 +1,5 @@
 use std::{
-    num::{NonZeroU64, ParseIntError},
+    num::NonZeroU64,
     str::FromStr,
     time::{Duration, UNIX_EPOCH},
 };
@@ -19,61 +19,244 @@ impl SecondsF64 {
     }
 }
 
-#[derive(
-    Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
-)]
-pub struct SecondsU64(u64);
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDurationError {
+    input: String,
+    reason: String,
+}
+
+impl std::fmt::Display for ParseDurationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid duration {:?}: {}", self.input, self.reason)
+    }
+}
+
+impl std::error::Error for ParseDurationError {}
+
+const DURATION_UNITS: [(&str, f64); 5] = [
+    ("ms", 1.0),
+    ("s", 1000.0),
+    ("m", 60.0 * 1000.0),
+    ("h", 60.0 * 60.0 * 1000.0),
+    ("d", 24.0 * 60.0 * 60.0 * 1000.0),
+];
+
+/// Parses a duration string into milliseconds.
+///
+/// A number without a unit is interpreted as seconds (e.g., `1`, `0.5`).
+/// Otherwise, the string is a sequence of numbers followed by one of
+/// the `ms`, `s`, `m`, `h` and `d` units (e.g., `500ms`, `90s`, `1h30m`, `2d`).
+pub fn parse_millis(s: &str) -> Result<u64, ParseDurationError> {
+    let error = |reason: &str| ParseDurationError {
+        input: s.to_owned(),
+        reason: reason.to_owned(),
+    };
+    if s.is_empty() {
+        return Err(error("empty string"));
+    }
+
+    let parse_number = |number: &str| {
+        number
+            .parse::<f64>()
+            .ok()
+            .filter(|_| number.starts_with(|c: char| c.is_ascii_digit()))
+            .ok_or_else(|| error("malformed number"))
+    };
 
-impl SecondsU64 {
-    pub const fn new(seconds: u64) -> Self {
-        Self(seconds)
+    let mut millis = 0.0;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let number_end = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(rest.len());
+        if number_end == 0 {
+            return Err(error("expected a number"));
+        }
+        let number = parse_number(&rest[..number_end])?;
+        rest = &rest[number_end..];
+
+        if number_end == s.len() {
+            // A bare number is interpreted as seconds.
+            millis = number * 1000.0;
+            break;
+        }
+
+        let unit_end = rest
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(rest.len());
+        let unit = &rest[..unit_end];
+        let Some((_, scale)) = DURATION_UNITS.iter().find(|(name, _)| *name == unit) else {
+            let reason = if unit.is_empty() {
+                "missing unit".to_owned()
+            } else {
+                format!("unknown unit {unit:?}")
+            };
+            return Err(error(&format!(
+                "{reason} (expected one of `ms`, `s`, `m`, `h` or `d`)"
+            )));
+        };
+        millis += number * scale;
+        rest = &rest[unit_end..];
+    }
+
+    let millis = millis.round();
+    if millis >= u64::MAX as f64 {
+        return Err(error("too large"));
+    }
+    Ok(millis as u64)
+}
+
+/// Duration with millisecond resolution.
+///
+/// In JSON, this is represented as a number of seconds (e.g., `0.25`) or a duration string (e.g., `"250ms"`).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MillisU64(u64);
+
+impl MillisU64 {
+    pub const fn new(millis: u64) -> Self {
+        Self(millis)
+    }
+
+    pub const fn from_secs(seconds: u64) -> Self {
+        Self(seconds * 1000)
     }
 
     pub const fn get(self) -> u64 {
         self.0
     }
 
+    pub fn as_secs_f64(self) -> f64 {
+        self.0 as f64 / 1000.0
+    }
+
     pub const fn to_duration(self) -> Duration {
-        Duration::from_secs(self.0)
+        Duration::from_millis(self.0)
     }
 }
 
-impl FromStr for SecondsU64 {
-    type Err = ParseIntError;
+impl FromStr for MillisU64 {
+    type Err = ParseDurationError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let v: u64 = s.parse()?;
-        Ok(Self(v))
+        parse_millis(s).map(Self)
+    }
+}
+
+impl Serialize for MillisU64 {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.0 % 1000 == 0 {
+            serializer.serialize_u64(self.0 / 1000)
+        } else {
+            serializer.serialize_f64(self.as_secs_f64())
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MillisU64 {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = MillisU64;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "a number of seconds or a duration string")
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                v.checked_mul(1000)
+                    .map(MillisU64)
+                    .ok_or_else(|| E::custom("too large duration"))
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                u64::try_from(v)
+                    .map_err(|_| E::custom("negative duration"))
+                    .and_then(|v| self.visit_u64(v))
+            }
+
+            fn visit_f64<E: serde::de::Error>(self, v: f64) -> Result<Self::Value, E> {
+                if !(0.0..u64::MAX as f64 / 1000.0).contains(&v) {
+                    return Err(E::custom("out of range duration"));
+                }
+                Ok(MillisU64((v * 1000.0).round() as u64))
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
-pub struct SecondsNonZeroU64(NonZeroU64);
+/// Non-zero duration with millisecond resolution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MillisNonZeroU64(NonZeroU64);
 
-impl SecondsNonZeroU64 {
-    pub const fn new(seconds: NonZeroU64) -> Self {
-        Self(seconds)
+impl MillisNonZeroU64 {
+    pub const fn new(millis: NonZeroU64) -> Self {
+        Self(millis)
     }
 
     pub const fn get(self) -> u64 {
         self.0.get()
     }
 
+    pub fn as_secs_f64(self) -> f64 {
+        self.0.get() as f64 / 1000.0
+    }
+
     pub const fn to_duration(self) -> Duration {
-        Duration::from_secs(self.0.get())
+        Duration::from_millis(self.0.get())
     }
 }
 
-impl FromStr for SecondsNonZeroU64 {
-    type Err = ParseIntError;
+impl FromStr for MillisNonZeroU64 {
+    type Err = ParseDurationError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let v: NonZeroU64 = s.parse()?;
-        Ok(Self(v))
+        let millis = parse_millis(s)?;
+        NonZeroU64::new(millis).map(Self).ok_or(ParseDurationError {
+            input: s.to_owned(),
+            reason: "must be at least 1ms".to_owned(),
+        })
     }
 }
 
-pub fn fmt_u64(mut n: u64) -> String {
+impl Serialize for MillisNonZeroU64 {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        MillisU64(self.0.get()).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for MillisNonZeroU64 {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let millis = MillisU64::deserialize(deserializer)?;
+        NonZeroU64::new(millis.0)
+            .map(Self)
+            .ok_or_else(|| serde::de::Error::custom("duration must be at least 1ms"))
+    }
+}
+
+/// Formats milliseconds as seconds (e.g., `1,234s`, `0.25s`).
+pub fn fmt_millis(millis: u64) -> String {
+    let seconds = fmt_u64(millis / 1000);
+    let fraction = millis % 1000;
+    if fraction == 0 {
+        format!("{seconds}s")
+    } else {
+        let fraction = format!("{fraction:03}");
+        format!("{seconds}.{}s", fraction.trim_end_matches('0'))
+    }
+}
+
+pub fn fmt_u64(n: u64) -> String {
+    fmt_u128(u128::from(n))
+}
+
+pub fn fmt_u128(mut n: u128) -> String {
     if n == 0 {
         return n.to_string();
     }
@@ -94,10 +277,14 @@ pub fn fmt_u64(mut n: u64) -> String {
 }
 
 pub fn fmt_i64(n: i64) -> String {
+    fmt_i128(i128::from(n))
+}
+
+pub fn fmt_i128(n: i128) -> String {
     if n < 0 {
-        format!("-{}", fmt_u64(n.unsigned_abs()))
+        format!("-{}", fmt_u128(n.unsigned_abs()))
     } else {
-        fmt_u64(n.unsigned_abs())
+        fmt_u128(n.unsigned_abs())
     }
 }
 
@@ -128,3 +315,46 @@ pub fn fmt_f64(n: f64, decimal_places: usize) -> String {
 
     s.into_iter().collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_duration() {
+        assert_eq!(parse_millis("1h30m"), Ok(90 * 60 * 1000));
+        assert_eq!(parse_millis("1d2h3m4s5ms"), Ok(93_784_005));
+        assert_eq!(parse_millis("250ms"), Ok(250));
+        assert_eq!(parse_millis("1.5s"), Ok(1500));
+
+        // A bare number is interpreted as seconds.
+        assert_eq!(parse_millis("0"), Ok(0));
+        assert_eq!(parse_millis("60"), Ok(60_000));
+        assert_eq!(parse_millis("0.25"), Ok(250));
+    }
+
+    #[test]
+    fn parse_invalid_duration() {
+        let reason = |s: &str| parse_millis(s).expect_err("invalid").reason;
+        assert!(reason("5y").starts_with("unknown unit \"y\""));
+        assert!(reason("1h30").starts_with("missing unit"));
+        assert_eq!(reason(""), "empty string");
+        assert_eq!(reason("-1s"), "expected a number");
+        assert_eq!(reason("1..5s"), "malformed number");
+        assert_eq!(reason("99999999999999999999d"), "too large");
+        assert!("0s".parse::<MillisNonZeroU64>().is_err());
+    }
+
+    #[test]
+    fn serde_duration() {
+        let d: MillisU64 = serde_json::from_str("\"1m30s\"").expect("valid");
+        assert_eq!(d.get(), 90_000);
+        assert_eq!(serde_json::to_string(&d).expect("valid"), "90");
+
+        let d: MillisU64 = serde_json::from_str("0.25").expect("valid");
+        assert_eq!(serde_json::to_string(&d).expect("valid"), "0.25");
+
+        assert!(serde_json::from_str::<MillisU64>("-1").is_err());
+        assert!(serde_json::from_str::<MillisNonZeroU64>("0").is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use std::{
-    path::PathBuf,
-    process::Command,
+    io::{ErrorKind, Read},
+    path::{Path, PathBuf},
+    process::{Child, Command, Output, Stdio},
     str::FromStr,
     sync::mpsc,
     time::{Duration, Instant},
@@ -8,15 +9,135 @@ use std::{
 
 use serde::{Deserialize, Serialize};
 
-use crate::{metrics::Record, num::SecondsF64};
+use crate::{
+    http::{HttpRequest, HttpUrl},
+    metrics::Record,
+    num::{MillisNonZeroU64, SecondsF64},
+};
+
+const COMMAND_WAIT_INTERVAL: Duration = Duration::from_millis(10);
+
+const MAX_ERROR_STDERR_LEN: usize = 1024;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PollTarget {
     pub target: String,
-    pub command_path: PathBuf,
 
-    #[serde(default, skip_serializing_if = "Vec::is_empty")]
-    pub command_args: Vec<String>,
+    #[serde(flatten)]
+    pub source: PollSource,
+
+    /// Polling interval for this target (overrides the global one).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval: Option<MillisNonZeroU64>,
+
+    /// Timeout for a single poll. If a command exceeds it, the command process is killed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<MillisNonZeroU64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged, try_from = "RawPollSource")]
+pub enum PollSource {
+    Command {
+        command_path: PathBuf,
+
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        command_args: Vec<String>,
+    },
+    Http(HttpRequest),
+}
+
+#[derive(Debug, Deserialize)]
+struct RawPollSource {
+    command_path: Option<PathBuf>,
+
+    #[serde(default)]
+    command_args: Vec<String>,
+
+    url: Option<HttpUrl>,
+
+    #[serde(default)]
+    method: Option<String>,
+
+    #[serde(default)]
+    headers: Vec<(String, String)>,
+
+    #[serde(default)]
+    body: Option<String>,
+}
+
+impl TryFrom<RawPollSource> for PollSource {
+    type Error = &'static str;
+
+    fn try_from(raw: RawPollSource) -> Result<Self, Self::Error> {
+        match (raw.command_path, raw.url) {
+            (Some(command_path), None) => Ok(Self::Command {
+                command_path,
+                command_args: raw.command_args,
+            }),
+            (None, Some(url)) => Ok(Self::Http(HttpRequest {
+                url,
+                method: raw.method,
+                headers: raw.headers,
+                body: raw.body,
+            })),
+            (Some(_), Some(_)) => Err("`command_path` and `url` cannot be specified together"),
+            (None, None) => Err("either `command_path` or `url` must be specified"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PollError {
+    pub kind: PollErrorKind,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+
+    /// Standard error output of the command (truncated if too long).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stderr: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl PollError {
+    fn new(kind: PollErrorKind) -> Self {
+        Self {
+            kind,
+            exit_code: None,
+            stderr: None,
+            message: None,
+        }
+    }
+
+    fn message(mut self, message: impl std::fmt::Display) -> Self {
+        self.message = Some(message.to_string());
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PollErrorKind {
+    /// Failed to execute the command.
+    Spawn,
+
+    /// The command exited with a non-zero status.
+    Exit,
+
+    /// The output was not a JSON value.
+    InvalidJson,
+
+    /// The poll did not finish within the timeout.
+    Timeout,
+
+    /// Failed to send the HTTP request or to receive the response.
+    Request,
+
+    /// The HTTP response status was not 2xx.
+    Status,
 }
 
 impl FromStr for PollTarget {
@@ -31,6 +152,8 @@ impl FromStr for PollTarget {
 pub struct Poller {
     target: PollTarget,
     poll_interval: Duration,
+    poll_timeout: Option<Duration>,
+    record_errors: bool,
     record_tx: mpsc::Sender<Record>,
     next_poll_time: Instant,
     end_time: Instant,
@@ -41,12 +164,18 @@ impl Poller {
         target: PollTarget,
         poll_interval: Duration,
         poll_duration: Duration,
+        poll_timeout: Option<Duration>,
+        record_errors: bool,
         record_tx: mpsc::Sender<Record>,
     ) {
         let now = Instant::now();
+        let poll_interval = target.interval.map_or(poll_interval, |t| t.to_duration());
+        let poll_timeout = target.timeout.map(|t| t.to_duration()).or(poll_timeout);
         let mut poller = Poller {
             target,
             poll_interval,
+            poll_timeout,
+            record_errors,
             record_tx,
             next_poll_time: now,
             end_time: now + poll_duration,
@@ -59,12 +188,22 @@ impl Poller {
             return false;
         }
 
-        if let Some(value) = self.poll() {
-            let record = Record {
+        let record = match self.poll() {
+            Ok(value) => Some(Record {
                 target: self.target.target.clone(),
                 timestamp: SecondsF64::timestamp(),
                 metrics: value,
-            };
+                error: None,
+            }),
+            Err(error) if self.record_errors => Some(Record {
+                target: self.target.target.clone(),
+                timestamp: SecondsF64::timestamp(),
+                metrics: serde_json::Value::Null,
+                error: Some(error),
+            }),
+            Err(_) => None,
+        };
+        if let Some(record) = record {
             if self.record_tx.send(record).is_err() {
                 return false;
             }
@@ -78,24 +217,47 @@ impl Poller {
         true
     }
 
-    fn poll(&self) -> Option<serde_json::Value> {
-        match Command::new(&self.target.command_path)
-            .args(self.target.command_args.iter())
-            .output()
-        {
+    fn poll(&self) -> Result<serde_json::Value, PollError> {
+        match &self.target.source {
+            PollSource::Command {
+                command_path,
+                command_args,
+            } => self.poll_command(command_path, command_args),
+            PollSource::Http(request) => self.poll_http(request),
+        }
+    }
+
+    fn poll_command(
+        &self,
+        command_path: &Path,
+        command_args: &[String],
+    ) -> Result<serde_json::Value, PollError> {
+        match self.execute_command(command_path, command_args) {
             Err(e) => {
                 eprintln!(
                     "[{}] Failed to execute command {:?}: {e}",
                     self.target.target,
-                    self.target.command_path.display()
+                    command_path.display()
+                );
+                Err(PollError::new(PollErrorKind::Spawn).message(e))
+            }
+            Ok(None) => {
+                eprintln!(
+                    "[{}] Command {:?} timed out after {:?} and was killed",
+                    self.target.target,
+                    command_path.display(),
+                    self.poll_timeout.unwrap_or_default()
                 );
-                None
+                Err(PollError::new(PollErrorKind::Timeout).message(format!(
+                    "timed out after {:?}",
+                    self.poll_timeout.unwrap_or_default()
+                )))
             }
-            Ok(output) if !output.status.success() => {
+            Ok(Some(output)) if !output.status.success() => {
                 eprintln!(
                     "[{}] Command {:?} exited abnormaly{}.\n\nSTDOUT:\n{}\n\nSTDERR:{}",
                     self.target.target,
-                    self.target.command_path.display(),
+                    command_path.display(),
                     if let Some(code) = output.status.code() {
                         format!(" with code {code}")
                     } else {
@@ -104,20 +266,290 @@ impl Poller {
                     String::from_utf8_lossy(&output.stdout),
                     String::from_utf8_lossy(&output.stderr)
                 );
-                None
+                Err(PollError {
+                    exit_code: output.status.code(),
+                    stderr: Some(truncate_stderr(&output.stderr)),
+                    ..PollError::new(PollErrorKind::Exit)
+                })
             }
-            Ok(output) => match serde_json::from_slice(&output.stdout) {
+            Ok(Some(output)) => match serde_json::from_slice(&output.stdout) {
                 Err(e) => {
                     eprintln!(
                         "[{}] Command {:?} output is not JSON: {e}\n\nSTDOUT:{}",
                         self.target.target,
-                        self.target.command_path.display(),
+                        command_path.display(),
                         String::from_utf8_lossy(&output.stdout)
                     );
-                    None
+                    Err(PollError::new(PollErrorKind::InvalidJson).message(e))
+                }
+                Ok(value) => Ok(value),
+            },
+        }
+    }
+
+    /// Executes the command and returns `Ok(None)` if it did not finish within `poll_timeout`.
+    fn execute_command(
+        &self,
+        command_path: &Path,
+        command_args: &[String],
+    ) -> std::io::Result<Option<Output>> {
+        let mut command = Command::new(command_path);
+        command.args(command_args);
+        let Some(timeout) = self.poll_timeout else {
+            return command.output().map(Some);
+        };
+
+        // Run the command in its own process group so that its descendants
+        // (e.g., the remote command under `ssh`) can be killed together on timeout.
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stdout = read_in_background(child.stdout.take());
+        let stderr = read_in_background(child.stderr.take());
+
+        let deadline = Instant::now() + timeout;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if deadline <= Instant::now() {
+                kill_process_group(&mut child);
+                let _ = child.wait();
+                // The pipes are closed once the whole group has exited, which lets the readers finish.
+                // They are still detached, in case a descendant has left the group and keeps the pipes open.
+                return Ok(None);
+            }
+            std::thread::sleep(COMMAND_WAIT_INTERVAL);
+        };
+
+        Ok(Some(Output {
+            status,
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+        }))
+    }
+
+    fn poll_http(&self, request: &HttpRequest) -> Result<serde_json::Value, PollError> {
+        match request.send(self.poll_timeout) {
+            Err(e) => {
+                eprintln!(
+                    "[{}] Failed to request {} {}: {e}",
+                    self.target.target,
+                    request.method(),
+                    request.url
+                );
+                let kind = if e.kind() == ErrorKind::TimedOut {
+                    PollErrorKind::Timeout
+                } else {
+                    PollErrorKind::Request
+                };
+                Err(PollError::new(kind).message(e))
+            }
+            Ok(response) if !response.is_success() => {
+                eprintln!(
+                    "[{}] Request {} {} failed with status {}.\n\nBODY:\n{}",
+                    self.target.target,
+                    request.method(),
+                    request.url,
+                    response.status,
+                    String::from_utf8_lossy(&response.body)
+                );
+                Err(PollError::new(PollErrorKind::Status)
+                    .message(format!("status {}", response.status)))
+            }
+            Ok(response) => match serde_json::from_slice(&response.body) {
+                Err(e) => {
+                    eprintln!(
+                        "[{}] Response of {} {} is not JSON: {e}\n\nBODY:\n{}",
+                        self.target.target,
+                        request.method(),
+                        request.url,
+                        String::from_utf8_lossy(&response.body)
+                    );
+                    Err(PollError::new(PollErrorKind::InvalidJson).message(e))
                 }
-                Ok(value) => Some(value),
+                Ok(value) => Ok(value),
             },
         }
     }
 }
+
+#[cfg(unix)]
+fn kill_process_group(child: &mut Child) {
+    // SAFETY: `kill()` has no memory safety requirements.
+    // The PID is the one of the group leader since the child was spawned with `process_group(0)`.
+    let result = unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) };
+    if result != 0 {
+        let _ = child.kill();
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_process_group(child: &mut Child) {
+    let _ = child.kill();
+}
+
+fn read_in_background<R>(reader: Option<R>) -> std::thread::JoinHandle<Vec<u8>>
+where
+    R: Read + Send + 'static,
+{
+    std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut reader) = reader {
+            let _ = reader.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+fn truncate_stderr(stderr: &[u8]) -> String {
+    let stderr = String::from_utf8_lossy(stderr);
+    let stderr = stderr.trim_end();
+    if stderr.len() <= MAX_ERROR_STDERR_LEN {
+        return stderr.to_owned();
+    }
+
+    let mut end = MAX_ERROR_STDERR_LEN;
+    while !stderr.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}...", &stderr[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{http::tests::serve_once, test_util::TempDir};
+
+    fn poller(target: &str, poll_timeout: Option<Duration>) -> Poller {
+        let now = Instant::now();
+        Poller {
+            target: target.parse().expect("valid target"),
+            poll_interval: Duration::from_secs(1),
+            poll_timeout,
+            record_errors: true,
+            record_tx: mpsc::channel().0,
+            next_poll_time: now,
+            end_time: now,
+        }
+    }
+
+    fn http_poller(url: &HttpUrl) -> Poller {
+        let target = format!(r#"{{"target":"api","url":"{url}"}}"#);
+        poller(&target, Some(Duration::from_secs(5)))
+    }
+
+    #[test]
+    fn poll_command_timeout_kills_descendants() {
+        let dir = TempDir::new("poller-timeout");
+        let marker = dir.path().join("marker");
+        let script = format!("(sleep 0.5; touch {}) & wait", marker.display());
+        let target = serde_json::json!({
+            "target": "slow",
+            "command_path": "sh",
+            "command_args": ["-c", script],
+        });
+        let error = poller(&target.to_string(), Some(Duration::from_millis(100)))
+            .poll()
+            .expect_err("error");
+        assert_eq!(error.kind, PollErrorKind::Timeout);
+
+        std::thread::sleep(Duration::from_millis(800));
+        assert!(!marker.exists(), "the grandchild process was not killed");
+    }
+
+    #[test]
+    fn reject_zero_timeout() {
+        assert!(r#"{"target":"x","command_path":"echo","timeout":0}"#
+            .parse::<PollTarget>()
+            .is_err());
+        assert!(r#"{"target":"x","command_path":"echo","timeout":"0ms"}"#
+            .parse::<PollTarget>()
+            .is_err());
+        assert!(r#"{"target":"x","command_path":"echo","timeout":"1ms"}"#
+            .parse::<PollTarget>()
+            .is_ok());
+    }
+
+    #[test]
+    fn parse_http_target() {
+        let target: PollTarget = r#"{"target":"api","url":"http://127.0.0.1:8080/stats","headers":[["X-A","1"],["X-A","2"]]}"#
+            .parse()
+            .expect("valid");
+        let PollSource::Http(request) = &target.source else {
+            panic!("not an HTTP target: {target:?}");
+        };
+        assert_eq!(request.headers.len(), 2);
+
+        assert!(r#"{"target":"api","url":"ftp://127.0.0.1/"}"#.parse::<PollTarget>().is_err());
+        assert!(
+            r#"{"target":"x","url":"http://127.0.0.1/","command_path":"echo"}"#
+                .parse::<PollTarget>()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn poll_http_content_length() {
+        let (url, server) = serve_once(b"HTTP/1.1 200 OK\r\nContent-Length: 9\r\n\r\n{\"a\":{}}\n");
+        let value = http_poller(&url).poll().expect("ok");
+        assert_eq!(value, serde_json::json!({"a": {}}));
+        server.join().expect("server");
+    }
+
+    #[test]
+    fn poll_http_chunked() {
+        let (url, server) = serve_once(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2\r\n[1\r\n3\r\n,2]\r\n0\r\n\r\n",
+        );
+        let value = http_poller(&url).poll().expect("ok");
+        assert_eq!(value, serde_json::json!([1, 2]));
+        server.join().expect("server");
+    }
+
+    #[test]
+    fn poll_http_error_status() {
+        let (url, server) =
+            serve_once(b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 2\r\n\r\n{}");
+        let error = http_poller(&url).poll().expect_err("error");
+        assert_eq!(error.kind, PollErrorKind::Status);
+        assert_eq!(error.message.as_deref(), Some("status 503"));
+        server.join().expect("server");
+    }
+
+    #[test]
+    fn poll_http_invalid_json() {
+        let (url, server) = serve_once(b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\nfoo");
+        let error = http_poller(&url).poll().expect_err("error");
+        assert_eq!(error.kind, PollErrorKind::InvalidJson);
+        server.join().expect("server");
+    }
+
+    #[test]
+    fn poll_http_timeout() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("bind");
+        let port = listener.local_addr().expect("local_addr").port();
+        let target = format!(r#"{{"target":"api","url":"http://127.0.0.1:{port}/"}}"#);
+        let error = poller(&target, Some(Duration::from_millis(100)))
+            .poll()
+            .expect_err("error");
+        assert_eq!(error.kind, PollErrorKind::Timeout);
+        drop(listener);
+    }
+
+    #[test]
+    fn poll_http_connection_refused() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("bind");
+        let port = listener.local_addr().expect("local_addr").port();
+        drop(listener);
+        let target = format!(r#"{{"target":"api","url":"http://127.0.0.1:{port}/"}}"#);
+        let error = poller(&target, Some(Duration::from_secs(5)))
+            .poll()
+            .expect_err("error");
+        assert_eq!(error.kind, PollErrorKind::Request);
+    }
+}
@@ -0,0 +1,39 @@
+//! Helpers shared by the unit tests.
+
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+
+/// Parses the arguments of a (sub)command (the command name is not included in `args`).
+pub fn parse_args<T: clap::Args>(args: &[&str]) -> Result<T, clap::Error> {
+    #[derive(Parser)]
+    struct Cli<T: clap::Args> {
+        #[clap(flatten)]
+        command: T,
+    }
+
+    Cli::try_parse_from(std::iter::once("magpies").chain(args.iter().copied())).map(|c| c.command)
+}
+
+/// Temporary directory that is removed when dropped.
+#[derive(Debug)]
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("magpies-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("create_dir_all");
+        Self(dir)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
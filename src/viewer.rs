@@ -1,13 +1,18 @@
-use std::{fs::File, time::Duration};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    io::Read,
+    time::Duration,
+};
 
 use crossterm::event::{self, KeyCode, KeyEvent, KeyEventKind};
 use orfail::OrFail;
 use ratatui::{
     layout::{Alignment, Constraint, Layout, Margin},
     prelude::{Buffer, Rect},
-    style::{Style, Stylize},
+    style::{Color, Style, Stylize},
     symbols::{border, Marker},
-    text::{Line, Text},
+    text::{Line, Span, Text},
     widgets::{
         self, block::Title, Axis, Block, Cell, Chart, Dataset, GraphType, Paragraph, Row,
         Scrollbar, ScrollbarOrientation, ScrollbarState, Table, TableState, Widget,
@@ -17,41 +22,133 @@ use ratatui::{
 use regex::Regex;
 
 use crate::{
-    jsonl::JsonlReader,
-    metrics::{Record, TimeSeries, TimeSeriesSegment},
-    num::{fmt_f64, fmt_u64, SecondsNonZeroU64, SecondsU64},
+    jsonl::{JsonlReader, Rewind},
+    metrics::{
+        AggSelector, AggregatedValue, CounterOptions, Number, Record, RepresentativeValue,
+        SegmentAgg, SegmentValue, TargetAgg, TimeSeries, TimeSeriesSegment,
+    },
+    num::{fmt_f64, fmt_millis, fmt_u64, MillisNonZeroU64, MillisU64},
 };
 
 const POLL_INTERVAL: Duration = Duration::from_millis(100);
 
+const TARGET_COLORS: [Color; 12] = [
+    Color::Cyan,
+    Color::Yellow,
+    Color::Green,
+    Color::Magenta,
+    Color::Red,
+    Color::Blue,
+    Color::LightCyan,
+    Color::LightYellow,
+    Color::LightGreen,
+    Color::LightMagenta,
+    Color::LightRed,
+    Color::LightBlue,
+];
+
+const MIN_CHART_HEIGHT: u16 = 6;
+
+// Maximum number of records kept to rebuild the time series when reading from stdin.
+const MAX_KEPT_RECORDS: usize = 100_000;
+
+const DISTRIBUTION_HEADERS: [&str; 6] = ["Min", "P50", "P90", "P99", "Max", "StdDev"];
+
 #[derive(Debug, Clone)]
 pub struct ViewerOptions {
     pub absolute_time: bool,
-    pub interval: SecondsNonZeroU64,
-    pub chart_time_window: SecondsNonZeroU64,
+    pub interval: MillisNonZeroU64,
+    pub carry_forward: MillisU64,
+    pub chart_time_window: MillisNonZeroU64,
     pub decimal_places: u8,
     pub metric_filter: Regex,
+    pub target_filter: Regex,
+    pub counter_options: CounterOptions,
+    pub segment_agg: AggSelector<SegmentAgg>,
+    pub target_agg: AggSelector<TargetAgg>,
+    pub chart_mode: ChartMode,
+    pub chart_max_targets: usize,
     pub chart_marker: Marker,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SortKey {
+    Name,
+    Value,
+
+    /// Absolute value of the delta.
+    Delta,
+}
+
+impl SortKey {
+    fn next(self) -> Self {
+        match self {
+            Self::Name => Self::Value,
+            Self::Value => Self::Delta,
+            Self::Delta => Self::Name,
+        }
+    }
+}
+
+impl std::fmt::Display for SortKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Name => write!(f, "name"),
+            Self::Value => write!(f, "value"),
+            Self::Delta => write!(f, "|delta|"),
+        }
+    }
+}
+
+/// What to plot in the chart.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ChartMode {
+    Value,
+    #[default]
+    Delta,
+
+    /// Both values and deltas (the deltas are rescaled to the y-axis of the values).
+    Both,
+}
+
+impl ChartMode {
+    fn next(self) -> Self {
+        match self {
+            Self::Value => Self::Delta,
+            Self::Delta => Self::Both,
+            Self::Both => Self::Value,
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            Self::Value => "Value",
+            Self::Delta => "Delta/s",
+            Self::Both => "Value & Delta/s",
+        }
+    }
+}
+
 #[derive(Debug)]
-pub struct Viewer {
+pub struct Viewer<R> {
     terminal: DefaultTerminal,
-    reader: JsonlReader<File>,
+    reader: JsonlReader<R>,
     exit: bool,
     app: ViewerApp,
     widget_state: ViewerWidgetState,
 }
 
-impl Viewer {
-    pub fn new(mut reader: JsonlReader<File>, options: ViewerOptions) -> orfail::Result<Self> {
+impl<R: Read + Rewind> Viewer<R> {
+    pub fn new(mut reader: JsonlReader<R>, options: ViewerOptions) -> orfail::Result<Self> {
         let mut terminal = ratatui::init();
         terminal.clear().or_fail()?;
 
         let mut app = ViewerApp::new(&options);
+        app.records = (!reader.can_rewind()).then(VecDeque::new);
         while let Some(record) = reader.read_item::<Record>().or_fail()? {
             app.insert_record(&record);
         }
+        app.update_invalid_lines(reader.invalid_line_count(), reader.last_invalid_line());
 
         Ok(Self {
             terminal,
@@ -68,6 +165,8 @@ impl Viewer {
         while !self.exit {
             let mut need_redraw = false;
             if event::poll(POLL_INTERVAL).or_fail()? {
+                // Keep `handle_key_event()` out of the match guard since it has side effects.
+                #[allow(clippy::collapsible_match)]
                 match event::read().or_fail()? {
                     event::Event::Key(key) => {
                         if self.handle_key_event(key).or_fail()? {
@@ -85,6 +184,12 @@ impl Viewer {
                 self.app.insert_record(&record);
                 need_redraw = true;
             }
+            if self.app.update_invalid_lines(
+                self.reader.invalid_line_count(),
+                self.reader.last_invalid_line(),
+            ) {
+                need_redraw = true;
+            }
 
             if need_redraw {
                 self.draw().or_fail()?;
@@ -96,16 +201,7 @@ impl Viewer {
 
     fn draw(&mut self) -> orfail::Result<()> {
         self.app.sync_state();
-
-        self.widget_state.agg_table_scroll = self
-            .widget_state
-            .agg_table_scroll
-            .content_length(self.app.current_segment().aggregated_values.len());
-
-        self.widget_state.values_table_scroll = self
-            .widget_state
-            .values_table_scroll
-            .content_length(self.app.current_segment().target_segment_values.len());
+        self.sync_selection();
 
         self.terminal
             .draw(|frame| {
@@ -116,16 +212,150 @@ impl Viewer {
         Ok(())
     }
 
+    fn handle_prompt_key_event(&mut self, key: KeyEvent) -> orfail::Result<()> {
+        let Some(prompt) = &mut self.app.prompt else {
+            return Ok(());
+        };
+        match key.code {
+            KeyCode::Esc => {
+                if prompt.kind == PromptKind::Search {
+                    self.app.search = None;
+                    self.widget_state.selected_key = prompt.origin.take();
+                    self.app.in_agg_table = true;
+                }
+                self.app.prompt = None;
+            }
+            KeyCode::Enter => {
+                if prompt.error.is_some() {
+                    return Ok(());
+                }
+                let Some(prompt) = self.app.prompt.take() else {
+                    return Ok(());
+                };
+                match prompt.kind {
+                    PromptKind::MetricFilter => {
+                        let filter = Regex::new(&prompt.input).expect("already validated");
+                        self.set_metric_filter(filter).or_fail()?;
+                    }
+                    PromptKind::Search => {}
+                }
+            }
+            KeyCode::Backspace => {
+                prompt.input.pop();
+                prompt.validate();
+                self.update_search();
+            }
+            KeyCode::Char(c) => {
+                prompt.input.push(c);
+                prompt.validate();
+                self.update_search();
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // Rebuilds the time series under the new filter by reading the records again from the beginning.
+    fn set_metric_filter(&mut self, filter: Regex) -> orfail::Result<()> {
+        if !self.reader.can_rewind() {
+            self.app.set_metric_filter(filter);
+            return Ok(());
+        }
+
+        self.reader.rewind().or_fail()?;
+        self.app.options.metric_filter = filter;
+        self.app.ts.clear();
+        while let Some(record) = self.reader.read_item::<Record>().or_fail()? {
+            self.app.insert_record(&record);
+        }
+        self.app.update_invalid_lines(
+            self.reader.invalid_line_count(),
+            self.reader.last_invalid_line(),
+        );
+        Ok(())
+    }
+
+    // Applies the input of the search prompt and moves to the first match from where the search started.
+    fn update_search(&mut self) {
+        let Some(prompt) = &self.app.prompt else {
+            return;
+        };
+        if prompt.kind != PromptKind::Search {
+            return;
+        }
+        self.app.search = search_regex(&prompt.input);
+        self.widget_state.selected_key = prompt.origin.clone();
+        self.app.in_agg_table = true;
+        self.sync_selection();
+        self.move_to_match(true, false);
+    }
+
+    // Selects the next (or previous) metric whose name matches the search pattern.
+    fn move_to_match(&mut self, forward: bool, skip_current: bool) {
+        let Some(search) = &self.app.search else {
+            return;
+        };
+        let keys = self
+            .app
+            .agg_row_keys(self.widget_state.selected_key.as_deref());
+        if keys.is_empty() {
+            return;
+        }
+        let current = self.widget_state.agg_table.selected().unwrap_or_default();
+        let start = usize::from(skip_current);
+        let found = (start..start + keys.len())
+            .map(|offset| {
+                if forward {
+                    (current + offset) % keys.len()
+                } else {
+                    (current + keys.len() * 2 - offset) % keys.len()
+                }
+            })
+            .find(|&i| search.is_match(&keys[i]));
+        if let Some(i) = found {
+            self.widget_state.selected_key = Some(keys[i].clone());
+            self.app.in_agg_table = true;
+            self.sync_selection();
+        }
+    }
+
     fn handle_key_event(&mut self, key: KeyEvent) -> orfail::Result<bool> {
         let mut need_redraw = false;
         if key.kind != KeyEventKind::Press {
             return Ok(need_redraw);
         }
 
+        if self.app.prompt.is_some() {
+            self.handle_prompt_key_event(key).or_fail()?;
+            return Ok(true);
+        }
+
         match key.code {
             KeyCode::Char('q') => {
                 self.exit = true;
             }
+            KeyCode::Char('/') => {
+                self.app.prompt = Some(Prompt::new(
+                    PromptKind::MetricFilter,
+                    self.app.options.metric_filter.as_str(),
+                ));
+                need_redraw = true;
+            }
+            KeyCode::Char('f') => {
+                let mut prompt = Prompt::new(PromptKind::Search, "");
+                prompt.origin = self.widget_state.selected_key.clone();
+                self.app.prompt = Some(prompt);
+                self.app.search = None;
+                need_redraw = true;
+            }
+            KeyCode::Char(']') => {
+                self.move_to_match(true, true);
+                need_redraw = true;
+            }
+            KeyCode::Char('[') => {
+                self.move_to_match(false, true);
+                need_redraw = true;
+            }
             KeyCode::Char('p') => {
                 self.app.go_to_prev_time();
                 need_redraw = true;
@@ -142,6 +372,50 @@ impl Viewer {
                 self.app.go_to_end_time();
                 need_redraw = true;
             }
+            KeyCode::Char('a') => {
+                self.app.cycle_target_agg();
+                need_redraw = true;
+            }
+            KeyCode::Char('c') => {
+                self.app.chart_mode = self.app.chart_mode.next();
+                need_redraw = true;
+            }
+            KeyCode::Char(' ') => {
+                if let Some(key) = self.app.selected_item_key(&self.widget_state) {
+                    let key = key.to_owned();
+                    if !self.app.marked_keys.remove(&key) {
+                        self.app.marked_keys.insert(key);
+                    }
+                    need_redraw = true;
+                }
+            }
+            KeyCode::Char('h') => {
+                if let Some(target) = self.app.selected_target(&self.widget_state) {
+                    let target = target.to_owned();
+                    self.app.toggle_hidden_target(&target);
+                    need_redraw = true;
+                }
+            }
+            KeyCode::Char('H') => {
+                self.app.show_all_targets();
+                need_redraw = true;
+            }
+            KeyCode::Char('o') => {
+                self.app.sort_key = self.app.sort_key.next();
+                need_redraw = true;
+            }
+            KeyCode::Char('r') => {
+                self.app.sort_desc = !self.app.sort_desc;
+                need_redraw = true;
+            }
+            KeyCode::Char('t') => {
+                self.app.overlay_targets = !self.app.overlay_targets;
+                need_redraw = true;
+            }
+            KeyCode::Char('d') => {
+                self.app.show_distribution = !self.app.show_distribution;
+                need_redraw = true;
+            }
             KeyCode::Right => {
                 self.app.in_agg_table = false;
                 need_redraw = true;
@@ -185,27 +459,86 @@ impl Viewer {
     }
 
     fn move_cursor(&mut self, delta: i16) {
-        let (table, scroll) = if self.app.in_agg_table {
+        let (items, table, anchor) = if self.app.in_agg_table {
             (
-                &mut self.widget_state.agg_table,
-                &mut self.widget_state.agg_table_scroll,
+                self.app
+                    .agg_row_keys(self.widget_state.selected_key.as_deref()),
+                &self.widget_state.agg_table,
+                &mut self.widget_state.selected_key,
             )
         } else {
             (
-                &mut self.widget_state.values_table,
-                &mut self.widget_state.values_table_scroll,
+                self.app.value_row_targets(
+                    self.widget_state.selected_key.as_deref(),
+                    self.widget_state.selected_target.as_deref(),
+                ),
+                &self.widget_state.values_table,
+                &mut self.widget_state.selected_target,
             )
         };
-        if delta < 0 {
-            table.scroll_up_by(delta.unsigned_abs());
-        } else {
-            table.scroll_down_by(delta as u16);
+        if items.is_empty() {
+            return;
         }
-        *scroll = (*scroll).position(table.selected().unwrap_or_default());
+        let i = table.selected().unwrap_or_default() as isize + delta as isize;
+        *anchor = Some(items[i.clamp(0, items.len() as isize - 1) as usize].clone());
+        self.sync_selection();
+    }
+
+    // Selects the rows of the anchored metric and target.
+    //
+    // The rows are tracked by name as their indices change when sorting or moving in time.
+    fn sync_selection(&mut self) {
+        let state = &mut self.widget_state;
+
+        let keys = self.app.agg_row_keys(state.selected_key.as_deref());
+        sync_table_selection(&mut state.agg_table, &mut state.selected_key, &keys);
+        state.agg_table_scroll = state
+            .agg_table_scroll
+            .content_length(keys.len())
+            .position(state.agg_table.selected().unwrap_or_default());
+
+        let targets = self.app.value_row_targets(
+            state.selected_key.as_deref(),
+            state.selected_target.as_deref(),
+        );
+        sync_table_selection(
+            &mut state.values_table,
+            &mut state.selected_target,
+            &targets,
+        );
+        state.values_table_scroll = state
+            .values_table_scroll
+            .content_length(targets.len())
+            .position(state.values_table.selected().unwrap_or_default());
     }
 }
 
-impl Drop for Viewer {
+fn sync_table_selection(table: &mut TableState, anchor: &mut Option<String>, items: &[String]) {
+    if items.is_empty() {
+        table.select(None);
+        return;
+    }
+    let i = anchor
+        .as_ref()
+        .and_then(|anchor| items.iter().position(|item| item == anchor))
+        .unwrap_or_else(|| table.selected().unwrap_or_default().min(items.len() - 1));
+    table.select(Some(i));
+    *anchor = Some(items[i].clone());
+}
+
+/// Returns the pattern for the metric search.
+///
+/// The input is interpreted as a regex if possible, otherwise as a literal substring.
+fn search_regex(input: &str) -> Option<Regex> {
+    if input.is_empty() {
+        return None;
+    }
+    Regex::new(input)
+        .or_else(|_| Regex::new(&regex::escape(input)))
+        .ok()
+}
+
+impl<R> Drop for Viewer<R> {
     fn drop(&mut self) {
         ratatui::restore();
     }
@@ -213,6 +546,10 @@ impl Drop for Viewer {
 
 #[derive(Debug)]
 pub struct ViewerWidgetState {
+    // Names of the selected metric and target.
+    selected_key: Option<String>,
+    selected_target: Option<String>,
+
     agg_table: TableState,
     agg_table_scroll: ScrollbarState,
     agg_table_height: u16,
@@ -224,6 +561,8 @@ pub struct ViewerWidgetState {
 impl ViewerWidgetState {
     fn new() -> Self {
         Self {
+            selected_key: None,
+            selected_target: None,
             agg_table: TableState::default().with_selected(0),
             agg_table_scroll: ScrollbarState::new(0),
             agg_table_height: 0,
@@ -238,34 +577,134 @@ impl ViewerWidgetState {
 pub struct ViewerApp {
     options: ViewerOptions,
     ts: TimeSeries,
-    current_time: SecondsU64,
-    base_time: SecondsU64,
+    current_time: MillisU64,
+    base_time: MillisU64,
     initialized: bool,
     empty_segment: TimeSeriesSegment,
     tail: bool,
     in_agg_table: bool,
+    show_distribution: bool,
+    chart_mode: ChartMode,
+    overlay_targets: bool,
+
+    // Colors of the targets in the overlaid chart (assigned in the order of appearance).
+    target_colors: BTreeMap<String, Color>,
+
+    // Metrics to be plotted together (as small multiples).
+    marked_keys: BTreeSet<String>,
+
+    sort_key: SortKey,
+    sort_desc: bool,
+
+    // The last records read so far (used to rebuild `ts` when the metric filter is changed).
+    //
+    // These are kept only if the records cannot be read again (i.e., stdin).
+    // To bound the memory usage, only the last `MAX_KEPT_RECORDS` records are kept.
+    records: Option<VecDeque<Record>>,
+
+    prompt: Option<Prompt>,
+
+    // Pattern of the metric search (only used to move the cursor and to highlight names).
+    search: Option<Regex>,
+    invalid_line_count: u64,
+    last_invalid_line: Option<u64>,
 }
 
 impl ViewerApp {
     fn new(options: &ViewerOptions) -> Self {
         Self {
             options: options.clone(),
-            ts: TimeSeries::new(options.interval),
-            current_time: SecondsU64::new(0),
-            base_time: SecondsU64::new(0),
+            ts: TimeSeries::new(
+                options.interval,
+                options.carry_forward,
+                options.counter_options.clone(),
+                options.segment_agg.clone(),
+                options.target_agg.clone(),
+            ),
+            current_time: MillisU64::new(0),
+            base_time: MillisU64::new(0),
             initialized: false,
             empty_segment: TimeSeriesSegment::empty(options.interval),
             tail: false,
             in_agg_table: true,
+            show_distribution: false,
+            chart_mode: options.chart_mode,
+            overlay_targets: false,
+            target_colors: BTreeMap::new(),
+            marked_keys: BTreeSet::new(),
+            sort_key: SortKey::Name,
+            sort_desc: false,
+            records: None,
+            prompt: None,
+            search: None,
+            invalid_line_count: 0,
+            last_invalid_line: None,
+        }
+    }
+
+    fn cycle_target_agg(&mut self) {
+        self.ts.target_agg.default = self.ts.target_agg.default.next();
+        self.ts.resync_state();
+    }
+
+    fn update_invalid_lines(&mut self, count: u64, last_invalid_line: Option<u64>) -> bool {
+        if self.invalid_line_count == count {
+            return false;
         }
+        self.invalid_line_count = count;
+        self.last_invalid_line = last_invalid_line;
+        true
     }
 
     fn insert_record(&mut self, record: &Record) {
+        if !self.options.target_filter.is_match(&record.target) {
+            return;
+        }
         self.ts.insert(record, &self.options.metric_filter);
+        if let Some(records) = &mut self.records {
+            if records.len() == MAX_KEPT_RECORDS {
+                records.pop_front();
+            }
+            records.push_back(record.clone());
+        }
+        if !self.target_colors.contains_key(&record.target) {
+            let color = TARGET_COLORS[self.target_colors.len() % TARGET_COLORS.len()];
+            self.target_colors.insert(record.target.clone(), color);
+        }
+    }
+
+    // Values and deltas cannot be plotted together when overlaying the targets.
+    fn effective_chart_mode(&self) -> ChartMode {
+        if self.overlay_targets && self.chart_mode == ChartMode::Both {
+            ChartMode::Value
+        } else {
+            self.chart_mode
+        }
+    }
+
+    fn toggle_hidden_target(&mut self, target: &str) {
+        if !self.ts.hidden_targets.remove(target) {
+            self.ts.hidden_targets.insert(target.to_owned());
+        }
+        self.ts.resync_state();
+    }
+
+    fn show_all_targets(&mut self) {
+        self.ts.hidden_targets.clear();
+        self.ts.resync_state();
+    }
+
+    // Rebuilds the time series from the records kept so far.
+    fn set_metric_filter(&mut self, filter: Regex) {
+        self.options.metric_filter = filter;
+        self.ts.clear();
+        for record in self.records.iter().flatten() {
+            self.ts.insert(record, &self.options.metric_filter);
+        }
     }
 
     fn go_to_prev_time(&mut self) {
-        self.current_time = SecondsU64::new(
+        self.current_time = MillisU64::new(
             self.current_time
                 .get()
                 .saturating_sub(self.options.interval.get()),
@@ -277,7 +716,7 @@ impl ViewerApp {
     }
 
     fn go_to_next_time(&mut self) {
-        self.current_time = SecondsU64::new(self.current_time.get() + self.options.interval.get())
+        self.current_time = MillisU64::new(self.current_time.get() + self.options.interval.get())
             .min(self.ts.last_start_time());
         if self.current_time == self.ts.last_start_time() {
             self.tail = true;
@@ -321,21 +760,26 @@ impl ViewerApp {
                 .checked_sub(self.options.interval.get())
             {
                 if self.current_time.get() < prev_last_start_time {
-                    self.current_time = SecondsU64::new(prev_last_start_time);
+                    self.current_time = MillisU64::new(prev_last_start_time);
                 }
             }
         }
     }
 
     fn calculate_layout(&self, area: Rect) -> (Rect, Rect, Rect, Rect, Rect) {
+        let header_height = self.status_lines().len().max(self.help_lines().len()) as u16 + 2;
         let [header_area, main_area] =
-            Layout::vertical([Constraint::Length(5), Constraint::Min(0)]).areas(area);
+            Layout::vertical([Constraint::Length(header_height), Constraint::Min(0)]).areas(area);
         let [status_area, help_area] =
             Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
                 .areas(header_area);
-        let [aggregation_area, main_right_area] =
-            Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
-                .areas(main_area);
+        // The distribution columns need more space.
+        let aggregation_width = if self.show_distribution { 75 } else { 50 };
+        let [aggregation_area, main_right_area] = Layout::horizontal([
+            Constraint::Percentage(aggregation_width),
+            Constraint::Percentage(100 - aggregation_width),
+        ])
+        .areas(main_area);
         let [values_area, chart_area] =
             Layout::vertical([Constraint::Percentage(50), Constraint::Percentage(50)])
                 .areas(main_right_area);
@@ -356,37 +800,91 @@ impl ViewerApp {
     }
 
     fn render_status(&self, area: Rect, buf: &mut Buffer) {
-        let segment = self.current_segment();
-
         let title = Title::from("Status".bold());
         let block = Block::bordered()
             .title(title.alignment(Alignment::Left))
             .border_set(border::THICK);
+        Paragraph::new(self.status_lines())
+            .left_aligned()
+            .block(block)
+            .render(area, buf);
+    }
 
-        let text = vec![
+    fn status_lines(&self) -> Vec<Line<'static>> {
+        let segment = self.current_segment();
+        let mut text = vec![
             Line::from(format!(
-                "Time:    {}s ~ {}s (between {}s ~ {}s)",
-                fmt_u64(segment.start_time.get() - self.base_time.get()),
-                fmt_u64(
+                "Time:    {} ~ {} (between {} ~ {})",
+                fmt_millis(segment.start_time.get() - self.base_time.get()),
+                fmt_millis(
                     segment.end_time().get().min(self.ts.end_time.get()) - self.base_time.get()
                 ),
-                fmt_u64(self.ts.start_time.get() - self.base_time.get()),
-                fmt_u64(self.ts.end_time.get() - self.base_time.get()),
+                fmt_millis(self.ts.start_time.get() - self.base_time.get()),
+                fmt_millis(self.ts.end_time.get() - self.base_time.get()),
             )),
             Line::from(format!(
-                "Targets: {}",
-                fmt_u64(segment.target_segment_values.len() as u64)
+                "Targets: {}/{} (filter={}{})",
+                fmt_u64(
+                    segment
+                        .target_segment_values
+                        .keys()
+                        .filter(|target| !self.ts.hidden_targets.contains(*target))
+                        .count() as u64
+                ),
+                fmt_u64(segment.target_segment_values.len() as u64),
+                self.options.target_filter,
+                if self.ts.hidden_targets.is_empty() {
+                    "".to_owned()
+                } else {
+                    format!(", hidden={}", fmt_u64(self.ts.hidden_targets.len() as u64))
+                }
             )),
             Line::from(format!(
                 "Metrics: {} (filter={})",
                 fmt_u64(segment.aggregated_values.len() as u64),
                 self.options.metric_filter
             )),
+            Line::from(format!(
+                "Agg:     {} in interval, {} across targets",
+                self.ts.segment_agg, self.ts.target_agg
+            )),
+            Line::from(format!(
+                "Chart:   {}{}",
+                self.effective_chart_mode().title(),
+                match (self.overlay_targets, self.chart_mode) {
+                    (false, _) => "",
+                    (true, ChartMode::Both) =>
+                        " by target (deltas are not plotted when overlaying)",
+                    (true, _) => " by target",
+                }
+            )),
+            Line::from(format!(
+                "Errors:  {} in interval, {} in total{}",
+                fmt_u64(segment.error_counts.values().sum()),
+                fmt_u64(self.ts.error_counts.values().sum()),
+                if self.ts.error_counts.is_empty() {
+                    "".to_owned()
+                } else {
+                    format!(
+                        " ({})",
+                        self.ts
+                            .error_counts
+                            .iter()
+                            .map(|(target, n)| format!("{target}={}", fmt_u64(*n)))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )
+                },
+            )),
         ];
-        Paragraph::new(text)
-            .left_aligned()
-            .block(block)
-            .render(area, buf);
+        if let Some(line) = self.last_invalid_line {
+            text.push(Line::from(format!(
+                "Skipped: {} invalid lines (last at line {})",
+                fmt_u64(self.invalid_line_count),
+                fmt_u64(line)
+            )));
+        }
+        text
     }
 
     fn render_help(&self, area: Rect, buf: &mut Buffer) {
@@ -394,7 +892,14 @@ impl ViewerApp {
         let block = Block::bordered()
             .title(title.alignment(Alignment::Left))
             .border_set(border::THICK);
-        let text = vec![
+        Paragraph::new(self.help_lines())
+            .left_aligned()
+            .block(block)
+            .render(area, buf);
+    }
+
+    fn help_lines(&self) -> Vec<Line<'static>> {
+        vec![
             Line::from(vec!["Quit: ".into(), "<Q>".bold()]),
             Line::from(vec![
                 "Time: ".into(),
@@ -411,60 +916,141 @@ impl ViewerApp {
                 "Move: ".into(),
                 "<Left>, <Right>, <Up>, <Down>, <PageUp>, <PageDown>".bold(),
             ]),
-        ];
-        Paragraph::new(text)
-            .left_aligned()
-            .block(block)
-            .render(area, buf);
+            Line::from(vec![
+                "Metric: ".into(),
+                "<Space>".bold(),
+                " mark, ".into(),
+                "</>".bold(),
+                " filter, ".into(),
+                "<F>".bold(),
+                "ind, ".into(),
+                "<[>/<]>".bold(),
+                " prev/next match".into(),
+            ]),
+            Line::from(vec![
+                "Target: ".into(),
+                "<H>".bold(),
+                "ide/show, ".into(),
+                "<Shift+H>".bold(),
+                " show all".into(),
+            ]),
+            Line::from(vec![
+                "Sort: ".into(),
+                "<O>".bold(),
+                "rder by, ".into(),
+                "<R>".bold(),
+                "everse".into(),
+            ]),
+            Line::from(vec![
+                "Mode: ".into(),
+                "<A>".bold(),
+                "gg, ".into(),
+                "<D>".bold(),
+                "istribution, ".into(),
+                "<C>".bold(),
+                "hart, ".into(),
+                "<T>".bold(),
+                "argets".into(),
+            ]),
+            Line::from(vec![
+                "Note: ".into(),
+                "</>".bold(),
+                if self.records.is_some() {
+                    format!(
+                        " rebuilds from the last {} records only",
+                        fmt_u64(MAX_KEPT_RECORDS as u64)
+                    )
+                    .into()
+                } else {
+                    " re-reads the file, but not rotated ones".into()
+                },
+            ]),
+        ]
     }
 
     fn render_aggregation(&self, area: Rect, buf: &mut Buffer, state: &mut ViewerWidgetState) {
-        let segment = self.current_segment();
-
-        let title = Title::from("Aggregated Metrics".bold());
+        let title = Title::from(format!("Aggregated Metrics{}", self.sort_title()).bold());
         let block = Block::bordered()
             .title(title.alignment(Alignment::Left))
             .border_set(border::THICK);
 
-        let header = ["Name", "Value", "Delta/s"]
+        let value_header = format!("Value ({})", self.ts.target_agg.default);
+        let mut headers = vec!["Name", &value_header, "Delta/s"];
+        let mut widths = vec![
+            Constraint::Percentage(50),
+            Constraint::Percentage(25),
+            Constraint::Percentage(25),
+        ];
+        if self.show_distribution {
+            headers.extend(DISTRIBUTION_HEADERS);
+            widths = vec![
+                Constraint::Fill(3),
+                Constraint::Fill(2),
+                Constraint::Fill(2),
+            ];
+            widths.extend([Constraint::Fill(1); DISTRIBUTION_HEADERS.len()]);
+        }
+        let header = headers
             .into_iter()
             .map(|t| Cell::from(Text::from(t).centered()))
             .collect::<Row>()
             .style(Style::default().bold())
             .height(1);
-        let rows = segment.aggregated_values.iter().map(|(name, agg_value)| {
-            [
-                Cell::from(Text::from(name.as_str())),
-                Cell::from(
-                    Text::from(agg_value.sum_text(self.options.decimal_places)).right_aligned(),
-                ),
-                Cell::from(
-                    Text::from(format!(
-                        "{}  ", // "  " is the padding for scroll bar
-                        agg_value.delta_text(self.options.decimal_places)
-                    ))
-                    .right_aligned(),
-                ),
-            ]
+        let selected_key = self.selected_item_key(state);
+        let rows = self
+            .agg_rows(selected_key)
             .into_iter()
-            .collect::<Row>()
-        });
-        let table = Table::new(
-            rows,
-            [
-                Constraint::Percentage(50),
-                Constraint::Percentage(25),
-                Constraint::Percentage(25),
-            ],
-        )
-        .header(header)
-        .column_spacing(1)
-        .highlight_style(if self.in_agg_table {
-            Style::new().reversed()
-        } else {
-            Style::new().bold()
-        })
-        .block(block);
+            .map(|(name, agg_value)| {
+                let marked = self.marked_keys.contains(name);
+                let agg = self.ts.target_agg.select(name);
+                let name = self.highlight_matches(name, if marked { "* " } else { "" });
+                let Some(agg_value) = agg_value else {
+                    return Row::new([Cell::from(name)]).style(Style::new().dim());
+                };
+                let mut value_text = agg_value.value_text(self.options.decimal_places);
+                if agg != self.ts.target_agg.default {
+                    // Overridden by `--target-agg-override`.
+                    value_text.push_str(&format!(" ({agg})"));
+                }
+                let mut texts = vec![
+                    value_text,
+                    agg_value.delta_text(self.options.decimal_places),
+                ];
+                if self.show_distribution {
+                    texts.extend(
+                        agg_value
+                            .distribution
+                            .as_ref()
+                            .map_or_else(Default::default, |d| {
+                                d.texts(self.options.decimal_places)
+                            }),
+                    );
+                }
+                if let Some(last) = texts.last_mut() {
+                    last.push_str("  "); // "  " is the padding for scroll bar
+                }
+                std::iter::once(Cell::from(name))
+                    .chain(
+                        texts
+                            .into_iter()
+                            .map(|text| Cell::from(Text::from(text).right_aligned())),
+                    )
+                    .collect::<Row>()
+                    .style(if marked {
+                        Style::new().bold()
+                    } else {
+                        Style::new()
+                    })
+            });
+        let table = Table::new(rows, widths)
+            .header(header)
+            .column_spacing(1)
+            .highlight_style(if self.in_agg_table {
+                Style::new().reversed()
+            } else {
+                Style::new().bold()
+            })
+            .block(block);
         widgets::StatefulWidget::render(table, area, buf, &mut state.agg_table);
 
         // Scrollbar
@@ -484,34 +1070,138 @@ impl ViewerApp {
         state.agg_table_height = area.height;
     }
 
-    fn selected_item_key(&self, state: &ViewerWidgetState) -> Option<&str> {
-        let segment = self.current_segment();
-        state
-            .agg_table
-            .selected()
-            .and_then(|i| segment.aggregated_values.keys().nth(i).map(|k| k.as_str()))
+    // Returns the metric name with the parts matching the search pattern highlighted.
+    fn highlight_matches(&self, name: &str, prefix: &'static str) -> Line<'static> {
+        let mut spans = vec![Span::from(prefix)];
+        let mut end = 0;
+        if let Some(search) = &self.search {
+            for m in search.find_iter(name).filter(|m| !m.is_empty()) {
+                spans.push(Span::from(name[end..m.start()].to_owned()));
+                spans.push(m.as_str().to_owned().black().on_yellow());
+                end = m.end();
+            }
+        }
+        spans.push(Span::from(name[end..].to_owned()));
+        Line::from(spans)
+    }
+
+    fn selected_item_key<'a>(&self, state: &'a ViewerWidgetState) -> Option<&'a str> {
+        state.selected_key.as_deref()
     }
 
-    fn selected_target(&self, state: &ViewerWidgetState) -> Option<&str> {
+    fn selected_target<'a>(&self, state: &'a ViewerWidgetState) -> Option<&'a str> {
         if self.in_agg_table {
             return None;
         }
+        state.selected_target.as_deref()
+    }
 
-        let segment = self.current_segment();
-        state.values_table.selected().and_then(|i| {
-            segment
-                .target_segment_values
-                .keys()
-                .nth(i)
-                .map(|k| k.as_str())
-        })
+    // Returns the rows of the aggregated table in display order.
+    //
+    // If the selected metric is missing in the current segment, it is kept as an empty row
+    // so that the selection does not jump to another metric.
+    fn agg_rows<'a>(
+        &'a self,
+        selected_key: Option<&'a str>,
+    ) -> Vec<(&'a str, Option<&'a AggregatedValue>)> {
+        let values = &self.current_segment().aggregated_values;
+        let mut rows: Vec<_> = values.iter().map(|(k, v)| (k.as_str(), Some(v))).collect();
+        if let Some(key) = selected_key {
+            if !values.contains_key(key) && self.options.metric_filter.is_match(key) {
+                rows.push((key, None));
+            }
+        }
+        self.sort_rows(&mut rows, |v| v.value.as_ref(), |v| v.delta);
+        rows
+    }
+
+    fn agg_row_keys(&self, selected_key: Option<&str>) -> Vec<String> {
+        self.agg_rows(selected_key)
+            .into_iter()
+            .map(|(k, _)| k.to_owned())
+            .collect()
+    }
+
+    // Returns the rows of the values table (i.e., the targets having the metric) in display order.
+    //
+    // As with `agg_rows()`, the selected target is kept even if it does not have the metric.
+    fn value_rows<'a>(
+        &'a self,
+        key: &str,
+        selected_target: Option<&'a str>,
+    ) -> Vec<(&'a str, Option<&'a SegmentValue>)> {
+        let mut rows: Vec<_> = self
+            .current_segment()
+            .target_segment_values
+            .iter()
+            .filter_map(|(target, values)| {
+                values.get(key).map(|value| (target.as_str(), Some(value)))
+            })
+            .collect();
+        if let Some(target) = selected_target {
+            if rows.iter().all(|(t, _)| *t != target) && self.options.target_filter.is_match(target)
+            {
+                rows.push((target, None));
+            }
+        }
+        self.sort_rows(&mut rows, |v| Some(&v.value), |v| v.delta);
+        rows
+    }
+
+    fn value_row_targets(&self, key: Option<&str>, selected_target: Option<&str>) -> Vec<String> {
+        key.map(|key| self.value_rows(key, selected_target))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(t, _)| t.to_owned())
+            .collect()
+    }
+
+    fn sort_rows<T, F0, F1>(&self, rows: &mut [(&str, Option<&T>)], value: F0, delta: F1)
+    where
+        F0: Fn(&T) -> Option<&RepresentativeValue>,
+        F1: Fn(&T) -> Option<Number>,
+    {
+        let sort_value = |v: Option<&T>| match self.sort_key {
+            SortKey::Name => None,
+            SortKey::Value => v
+                .and_then(&value)
+                .and_then(|v| v.as_number())
+                .map(|v| v.as_f64()),
+            SortKey::Delta => v.and_then(&delta).map(|v| v.as_f64().abs()),
+        };
+        rows.sort_by(|(name0, v0), (name1, v1)| {
+            let order = match (self.sort_key, sort_value(*v0), sort_value(*v1)) {
+                (SortKey::Name, _, _) => name0.cmp(name1),
+                (_, Some(a), Some(b)) => a.total_cmp(&b),
+                // Rows without numeric values are always placed at the end.
+                (_, Some(_), None) => return Ordering::Less,
+                (_, None, Some(_)) => return Ordering::Greater,
+                (_, None, None) => return name0.cmp(name1),
+            };
+            if self.sort_desc {
+                order.reverse()
+            } else {
+                order
+            }
+            .then_with(|| name0.cmp(name1))
+        });
+    }
+
+    fn sort_title(&self) -> String {
+        if self.sort_key == SortKey::Name && !self.sort_desc {
+            return "".to_owned();
+        }
+        format!(
+            " (sorted by {}, {})",
+            self.sort_key,
+            if self.sort_desc { "desc" } else { "asc" }
+        )
     }
 
     fn render_values(&self, area: Rect, buf: &mut Buffer, state: &mut ViewerWidgetState) {
-        let segment = self.current_segment();
         let key = self.selected_item_key(state);
         let title = if let Some(key) = key {
-            Title::from(format!("Metrics of {key:?}").bold())
+            Title::from(format!("Metrics of {key:?}{}", self.sort_title()).bold())
         } else {
             Title::from("Metrics".bold())
         };
@@ -527,27 +1217,47 @@ impl ViewerApp {
             .style(Style::default().bold())
             .height(1);
         let rows = key.iter().flat_map(|key| {
-            segment
-                .target_segment_values
-                .iter()
-                .filter_map(|(target, values)| {
-                    values.get(*key).map(|value| {
-                        [
-                            Cell::from(Text::from(target.as_str())),
-                            Cell::from(
-                                Text::from(value.value_text(self.options.decimal_places))
-                                    .right_aligned(),
-                            ),
-                            Cell::from(
-                                Text::from(format!(
-                                    "{}  ", // "  " is the padding for scroll bar
-                                    value.delta_text(self.options.decimal_places)
-                                ))
+            self.value_rows(key, state.selected_target.as_deref())
+                .into_iter()
+                .map(|(target, value)| {
+                    let hidden = self.ts.hidden_targets.contains(target);
+                    let target = if hidden {
+                        format!("{target} (hidden)")
+                    } else {
+                        target.to_owned()
+                    };
+                    let Some(value) = value else {
+                        return Row::new([Cell::from(Text::from(target))])
+                            .style(Style::new().dim());
+                    };
+                    [
+                        Cell::from(Text::from(target)),
+                        Cell::from(
+                            Text::from(value.value_text(self.options.decimal_places))
                                 .right_aligned(),
-                            ),
-                        ]
-                        .into_iter()
-                        .collect::<Row>()
+                        ),
+                        Cell::from(
+                            Text::from(format!(
+                                "{}{}  ", // "  " is the padding for scroll bar
+                                if value.reset { "↺ " } else { "" },
+                                value.delta_text(self.options.decimal_places)
+                            ))
+                            .right_aligned(),
+                        )
+                        .style(if value.reset {
+                            Style::new().yellow()
+                        } else {
+                            Style::new()
+                        }),
+                    ]
+                    .into_iter()
+                    .collect::<Row>()
+                    .style(if hidden {
+                        Style::new().dark_gray()
+                    } else if value.is_carried() {
+                        Style::new().dim()
+                    } else {
+                        Style::new()
                     })
                 })
         });
@@ -587,111 +1297,147 @@ impl ViewerApp {
     }
 
     fn render_chart(&self, area: Rect, buf: &mut Buffer, state: &ViewerWidgetState) {
-        let key = self.selected_item_key(state);
         let target = self.selected_target(state);
+        if self.marked_keys.is_empty() {
+            let key = self.selected_item_key(state);
+            self.render_metric_chart(area, buf, key, target, 0);
+            return;
+        }
 
-        let title = if let Some(key) = key {
-            Title::from(
-                format!(
-                    "Delta/s Chart of {key:?}{}",
-                    if let Some(t) = target {
-                        format!(" of {t:?}")
-                    } else {
-                        "".to_owned()
-                    }
-                )
-                .bold(),
-            )
-        } else {
-            Title::from("Delta/s Chart".bold())
-        };
-
-        let block = Block::bordered()
-            .title(title.alignment(Alignment::Left))
-            .border_set(border::THICK);
+        // Small multiples of the marked metrics (as many as fit in the area).
+        // The number of the remaining ones is shown in the title of the last chart.
+        let n = self
+            .marked_keys
+            .len()
+            .min((area.height / MIN_CHART_HEIGHT).max(1) as usize);
+        let areas = Layout::vertical(vec![Constraint::Ratio(1, n as u32); n]).split(area);
+        for (i, (key, area)) in self.marked_keys.iter().zip(areas.iter()).enumerate() {
+            let more = if i + 1 == n {
+                self.marked_keys.len() - n
+            } else {
+                0
+            };
+            self.render_metric_chart(*area, buf, Some(key), target, more);
+        }
+    }
 
+    fn render_metric_chart(
+        &self,
+        area: Rect,
+        buf: &mut Buffer,
+        key: Option<&str>,
+        target: Option<&str>,
+        more_charts: usize,
+    ) {
         let base_time = self.base_time.get();
         let end_time = self.current_time.get();
         let start_time = end_time
-            .saturating_sub(
-                self.options.interval.get() * self.options.chart_time_window.get()
-                    / self.options.interval.get(),
-            )
+            .saturating_sub(self.options.chart_time_window.get())
             .max(base_time);
 
-        let mut y_min = f64::INFINITY;
-        let mut y_max = f64::NEG_INFINITY;
-        let mut data = Vec::new();
-        for t in start_time..=end_time {
-            let Some(segment) = self.ts.segments.get(&SecondsU64::new(t)) else {
-                continue;
-            };
-
-            let delta = if self.in_agg_table {
-                key.and_then(|k| segment.aggregated_values.get(k))
-                    .and_then(|v| v.delta.as_ref().and_then(|v| v.as_f64()))
-            } else {
-                key.and_then(|k| {
-                    target
-                        .and_then(|t| {
-                            segment
-                                .target_segment_values
-                                .get(t)
-                                .and_then(|values| values.get(k))
-                        })
-                        .and_then(|v| v.delta.as_ref().and_then(|v| v.as_f64()))
-                })
-            };
-
-            let Some(y) = delta else {
-                continue;
+        let mut title = format!("{} Chart", self.chart_mode.title());
+        let mut series = Vec::new();
+        if let (Some(key), true) = (key, self.overlay_targets) {
+            let delta = self.effective_chart_mode() == ChartMode::Delta;
+            let (target_series, total) =
+                self.target_series(key, target, start_time, end_time, delta);
+            title = format!(
+                "{} Chart of {key:?} by Target{}",
+                if delta { "Delta/s" } else { "Value" },
+                if target_series.len() < total {
+                    format!(" (top {} of {total})", target_series.len())
+                } else {
+                    "".to_owned()
+                }
+            );
+            series = target_series;
+        } else if let Some(key) = key {
+            title = format!(
+                "{title} of {key:?}{}",
+                if let Some(t) = target {
+                    format!(" of {t:?}")
+                } else {
+                    "".to_owned()
+                }
+            );
+            let points = |delta: bool| {
+                self.ts
+                    .segments
+                    .range(MillisU64::new(start_time)..=MillisU64::new(end_time))
+                    .filter_map(|(t, segment)| {
+                        let y = self.chart_y(segment, key, target, delta)?;
+                        Some((t.get() as f64, y))
+                    })
+                    .collect::<Vec<_>>()
             };
-            data.push((t as f64, y));
-
-            y_min = y_min.min(y);
-            y_max = y_max.max(y);
-        }
-        if y_min.is_infinite() {
-            y_min = -1.0;
-            y_max = 1.0;
-        }
-        if y_min == y_max {
-            let v = y_min;
-            y_min = v - 1.0;
-            y_max = v + 1.0;
+            match self.chart_mode {
+                ChartMode::Value => series.push((None, points(false), Style::new())),
+                ChartMode::Delta => series.push((None, points(true), Style::new())),
+                ChartMode::Both => {
+                    let data = points(false);
+                    let [y_min, y_max] = y_bounds(data.iter());
+
+                    // There is only one y-axis, so deltas are rescaled to fit the range of the values
+                    // and their actual range is shown in the legend instead.
+                    let delta_data = points(true);
+                    let [d_min, d_max] = y_bounds(delta_data.iter());
+                    let scaled_delta_data = delta_data
+                        .iter()
+                        .map(|&(t, d)| (t, y_min + (d - d_min) / (d_max - d_min) * (y_max - y_min)))
+                        .collect::<Vec<_>>();
+                    let [d_min_label, d_max_label] =
+                        y_labels(d_min, d_max, self.options.decimal_places);
+
+                    series.push((Some("Value".to_owned()), data, Style::new().cyan()));
+                    series.push((
+                        Some(format!(
+                            "Delta/s ({} ~ {})",
+                            d_min_label.trim_start(),
+                            d_max_label.trim_start()
+                        )),
+                        scaled_delta_data,
+                        Style::new().yellow(),
+                    ));
+                }
+            }
         }
 
-        let decimal_places = if y_min.fract() == 0.0 && y_max.fract() == 0.0 {
-            0
-        } else {
-            self.options.decimal_places as usize
-        };
-
-        let datasets = vec![Dataset::default()
-            .marker(self.options.chart_marker)
-            .graph_type(GraphType::Line)
-            .data(&data)];
-
-        let mut y_min_label = fmt_f64(y_min, decimal_places);
-        let mut y_max_label = fmt_f64(y_max, decimal_places);
-        match y_min_label.len().cmp(&y_max_label.len()) {
-            std::cmp::Ordering::Equal => {}
-            std::cmp::Ordering::Less => {
-                y_min_label = format!("{y_min_label:>width$}", width = y_max_label.len());
-            }
-            std::cmp::Ordering::Greater => {
-                y_max_label = format!("{y_max_label:>width$}", width = y_min_label.len());
-            }
+        let mut block = Block::bordered()
+            .title(Title::from(title.bold()).alignment(Alignment::Left))
+            .border_set(border::THICK);
+        if more_charts > 0 {
+            block = block.title(
+                Title::from(format!("+{more_charts} more marked").bold())
+                    .alignment(Alignment::Right),
+            );
         }
 
+        let [y_min, y_max] = y_bounds(series.iter().flat_map(|(_, data, _)| data));
+        let datasets = series
+            .iter()
+            .map(|(name, data, style)| {
+                let dataset = Dataset::default()
+                    .marker(self.options.chart_marker)
+                    .graph_type(GraphType::Line)
+                    .style(*style)
+                    .data(data);
+                if let Some(name) = name {
+                    dataset.name(name.clone())
+                } else {
+                    dataset
+                }
+            })
+            .collect();
+
+        let [y_min_label, y_max_label] = y_labels(y_min, y_max, self.options.decimal_places);
         let chart = Chart::new(datasets)
             .x_axis(
                 Axis::default()
                     .style(Style::default().gray())
                     .bounds([start_time as f64, end_time as f64])
                     .labels([
-                        format!("{}s", fmt_u64(start_time - base_time)).bold(),
-                        format!("{}s", fmt_u64(end_time - base_time)).bold(),
+                        fmt_millis(start_time - base_time).bold(),
+                        fmt_millis(end_time - base_time).bold(),
                     ]),
             )
             .y_axis(
@@ -700,9 +1446,144 @@ impl ViewerApp {
                     .bounds([y_min, y_max])
                     .labels([y_min_label.bold(), y_max_label.bold()]),
             )
+            .hidden_legend_constraints((Constraint::Ratio(1, 2), Constraint::Ratio(1, 1)))
             .block(block);
         chart.render(area, buf);
     }
+
+    // Returns the series of the targets to overlay and the total number of the targets.
+    //
+    // If there are more than `--chart-max-targets` targets, only the ones having the largest
+    // absolute values are returned. The selected target is always included and highlighted.
+    fn target_series(
+        &self,
+        key: &str,
+        selected_target: Option<&str>,
+        start_time: u64,
+        end_time: u64,
+        delta: bool,
+    ) -> (Vec<ChartSeries>, usize) {
+        let segments = self
+            .ts
+            .segments
+            .range(MillisU64::new(start_time)..=MillisU64::new(end_time));
+        let targets = segments
+            .clone()
+            .flat_map(|(_, segment)| segment.target_segment_values.keys())
+            .filter(|target| !self.ts.hidden_targets.contains(*target))
+            .collect::<BTreeSet<_>>();
+
+        let mut series = targets
+            .iter()
+            .map(|target| {
+                let data = segments
+                    .clone()
+                    .filter_map(|(t, segment)| {
+                        let y = self.chart_y(segment, key, Some(target), delta)?;
+                        Some((t.get() as f64, y))
+                    })
+                    .collect::<Vec<_>>();
+                (target.as_str(), data)
+            })
+            .filter(|(_, data)| !data.is_empty())
+            .collect::<Vec<_>>();
+        let total = series.len();
+
+        let magnitude = |data: &[(f64, f64)]| data.iter().map(|(_, y)| y.abs()).fold(0.0, f64::max);
+        series.sort_by(|a, b| {
+            let selected = |target| Some(target) == selected_target;
+            selected(b.0)
+                .cmp(&selected(a.0))
+                .then_with(|| magnitude(&b.1).total_cmp(&magnitude(&a.1)))
+        });
+        series.truncate(self.options.chart_max_targets);
+
+        // The selected target is drawn last so that it is not hidden by the others.
+        series.reverse();
+        let series = series
+            .into_iter()
+            .map(|(target, data)| {
+                let color = self
+                    .target_colors
+                    .get(target)
+                    .copied()
+                    .unwrap_or(TARGET_COLORS[0]);
+                let style = match selected_target {
+                    Some(t) if t == target => Style::new().fg(color).bold(),
+                    Some(_) => Style::new().dark_gray(),
+                    None => Style::new().fg(color),
+                };
+                (Some(target.to_owned()), data, style)
+            })
+            .collect();
+        (series, total)
+    }
+
+    // If `target` is `None`, the aggregated value is returned.
+    fn chart_y(
+        &self,
+        segment: &TimeSeriesSegment,
+        key: &str,
+        target: Option<&str>,
+        delta: bool,
+    ) -> Option<f64> {
+        let (value, delta_value) = if let Some(target) = target {
+            let v = segment.target_segment_values.get(target)?.get(key)?;
+            (Some(&v.value), v.delta)
+        } else {
+            let v = segment.aggregated_values.get(key)?;
+            (v.value.as_ref(), v.delta)
+        };
+        if delta {
+            delta_value.map(|v| v.as_f64())
+        } else {
+            value.and_then(|v| v.as_number()).map(|v| v.as_f64())
+        }
+    }
+}
+
+// Legend name, points and style of a line in the chart.
+type ChartSeries = (Option<String>, Vec<(f64, f64)>, Style);
+
+fn y_bounds<'a>(data: impl Iterator<Item = &'a (f64, f64)>) -> [f64; 2] {
+    let mut y_min = f64::INFINITY;
+    let mut y_max = f64::NEG_INFINITY;
+    for &(_, y) in data {
+        y_min = y_min.min(y);
+        y_max = y_max.max(y);
+    }
+    if y_min.is_infinite() {
+        y_min = -1.0;
+        y_max = 1.0;
+    }
+    if y_min == y_max {
+        let v = y_min;
+        y_min = v - 1.0;
+        y_max = v + 1.0;
+    }
+    [y_min, y_max]
+}
+
+// Returns the labels for the bounds of a y-axis, padded to the same width.
+fn y_labels(y_min: f64, y_max: f64, decimal_places: u8) -> [String; 2] {
+    let decimal_places = if y_min.fract() == 0.0 && y_max.fract() == 0.0 {
+        0
+    } else {
+        decimal_places as usize
+    };
+
+    let mut y_min_label = fmt_f64(y_min, decimal_places);
+    let mut y_max_label = fmt_f64(y_max, decimal_places);
+    match y_min_label.len().cmp(&y_max_label.len()) {
+        std::cmp::Ordering::Equal => {}
+        std::cmp::Ordering::Less => {
+            y_min_label = format!("{y_min_label:>width$}", width = y_max_label.len());
+        }
+        std::cmp::Ordering::Greater => {
+            y_max_label = format!("{y_max_label:>width$}", width = y_min_label.len());
+        }
+    }
+    [y_min_label, y_max_label]
 }
 
 impl widgets::StatefulWidget for &ViewerApp {
@@ -716,5 +1597,78 @@ impl widgets::StatefulWidget for &ViewerApp {
         self.render_aggregation(aggregation_area, buf, state);
         self.render_values(values_area, buf, state);
         self.render_chart(chart_area, buf, state);
+        if let Some(prompt) = &self.prompt {
+            prompt.render(area, buf);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PromptKind {
+    MetricFilter,
+    Search,
+}
+
+/// Single-line input box shown at the bottom of the viewer.
+#[derive(Debug)]
+struct Prompt {
+    kind: PromptKind,
+    input: String,
+    error: Option<String>,
+
+    // Selected metric when the prompt was opened (restored when a search is canceled).
+    origin: Option<String>,
+}
+
+impl Prompt {
+    fn new(kind: PromptKind, input: &str) -> Self {
+        let mut this = Self {
+            kind,
+            input: input.to_owned(),
+            error: None,
+            origin: None,
+        };
+        this.validate();
+        this
+    }
+
+    fn title(&self) -> &'static str {
+        match self.kind {
+            PromptKind::MetricFilter => "Metric Filter (regex)",
+            PromptKind::Search => "Search Metrics (substring or regex)",
+        }
+    }
+
+    fn validate(&mut self) {
+        self.error = match self.kind {
+            PromptKind::MetricFilter => Regex::new(&self.input).err().map(|e| e.to_string()),
+            PromptKind::Search => None,
+        };
+    }
+
+    fn render(&self, area: Rect, buf: &mut Buffer) {
+        let mut text = vec![Line::from(vec![
+            "/".bold(),
+            self.input.clone().into(),
+            "█".into(),
+        ])];
+        if let Some(error) = &self.error {
+            // Only the last line of a regex error message is shown as it contains the reason.
+            let reason = error.lines().last().unwrap_or_default().trim();
+            text.push(Line::from(reason.to_owned().red()));
+        }
+
+        let height = text.len() as u16 + 2;
+        let [_, prompt_area] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(height)]).areas(area);
+        let title = Title::from(format!("{} (<Enter>: apply, <Esc>: cancel)", self.title()).bold());
+        let block = Block::bordered()
+            .title(title.alignment(Alignment::Left))
+            .border_set(border::THICK);
+        widgets::Clear.render(prompt_area, buf);
+        Paragraph::new(text)
+            .left_aligned()
+            .block(block)
+            .render(prompt_area, buf);
     }
 }